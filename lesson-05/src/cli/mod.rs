@@ -0,0 +1,423 @@
+// Пакетный режим бинарника: размеры, цвета и команды задаются аргументами
+// и файлами, результат пишется в stdout, ошибки отражаются в коде выхода.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::iter;
+use std::path::PathBuf;
+
+use crate::matrix::NetpbmFormat;
+use crate::palette::{Palette, PaletteError};
+use crate::render::{AnsiRenderer, AsciiRenderer, EmojiRenderer, HalfBlockRenderer, Renderer};
+use crate::script::{self, ParseError};
+use crate::{
+    create_display_with_palette, execute_commands, process_commands, Display, DisplayError,
+};
+
+pub const EXIT_IO: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_PARSE: i32 = 3;
+pub const EXIT_BOUNDS: i32 = 4;
+pub const EXIT_COLOUR: i32 = 5;
+/// Прочие ошибки команд: неизвестный опкод, оборванная команда, неверный аргумент
+pub const EXIT_COMMAND: i32 = 6;
+
+pub const USAGE: &str = "\
+Usage: display --width W --height H [options]
+
+Options:
+  --width W                 display width
+  --height H                display height
+  --default-colour C        background colour, index or palette name (default: first colour)
+  --palette FILE            palette file ('index name #rrggbb' per line)
+  --script FILE             read commands from FILE instead of stdin
+  --output-format FORMAT    emoji, ansi, ascii, halfblock, svg or ppm (default: emoji)
+  --quiet                   only validate and apply commands, print nothing
+  --help                    show this message
+
+Each input line is either a numeric command stream (1 2 2 2 3) or a script
+command (PAINT blue).
+
+Exit codes: 1 i/o, 2 usage, 3 parse, 4 out of bounds, 5 bad colour, 6 other command errors.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Emoji,
+    Ansi,
+    Ascii,
+    HalfBlock,
+    Svg,
+    Ppm,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub width: u32,
+    pub height: u32,
+    pub default_colour: Option<String>,
+    pub palette: Option<PathBuf>,
+    pub script: Option<PathBuf>,
+    pub output_format: OutputFormat,
+    pub quiet: bool,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    Io(io::Error),
+    Palette(PaletteError),
+    Parse(ParseError),
+    UnknownColour(String),
+    Display { line: usize, error: DisplayError },
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::Io(_) | CliError::Palette(PaletteError::Io(_)) => EXIT_IO,
+            CliError::Palette(PaletteError::Syntax { .. }) | CliError::Parse(_) => EXIT_PARSE,
+            CliError::UnknownColour(_) => EXIT_COLOUR,
+            CliError::Display { error, .. } => match error {
+                DisplayError::OutOfBounds { .. } | DisplayError::StepOutOfBounds { .. } => {
+                    EXIT_BOUNDS
+                }
+                DisplayError::InvalidColour { .. } => EXIT_COLOUR,
+                _ => EXIT_COMMAND,
+            },
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{message}"),
+            CliError::Io(err) => write!(f, "i/o error: {err}"),
+            CliError::Palette(err) => write!(f, "palette: {err}"),
+            CliError::Parse(err) => err.fmt(f),
+            CliError::UnknownColour(colour) => write!(f, "no such colour '{colour}'"),
+            CliError::Display { line, error } => write!(f, "line {line}: {error}"),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+impl From<io::Error> for CliError {
+    fn from(err: io::Error) -> Self {
+        CliError::Io(err)
+    }
+}
+
+/// Разбирает аргументы без имени программы. None означает --help
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Options>, CliError> {
+    let mut args = args.into_iter();
+    let mut width = None;
+    let mut height = None;
+    let mut options = Options {
+        width: 0,
+        height: 0,
+        default_colour: None,
+        palette: None,
+        script: None,
+        output_format: OutputFormat::Emoji,
+        quiet: false,
+    };
+    while let Some(arg) = args.next() {
+        // Поддерживаются и `--flag value`, и `--flag=value`
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::Usage(format!("{flag} expects a value")))
+        };
+        match flag.as_str() {
+            "--help" | "-h" => return Ok(None),
+            "--quiet" | "-q" => options.quiet = true,
+            "--width" => width = Some(parse_size(&flag, &value()?)?),
+            "--height" => height = Some(parse_size(&flag, &value()?)?),
+            "--default-colour" | "--default-color" => options.default_colour = Some(value()?),
+            "--palette" => options.palette = Some(value()?.into()),
+            "--script" => options.script = Some(value()?.into()),
+            "--output-format" => {
+                let format = value()?;
+                options.output_format = match format.as_str() {
+                    "emoji" => OutputFormat::Emoji,
+                    "ansi" => OutputFormat::Ansi,
+                    "ascii" => OutputFormat::Ascii,
+                    "halfblock" => OutputFormat::HalfBlock,
+                    "svg" => OutputFormat::Svg,
+                    "ppm" => OutputFormat::Ppm,
+                    _ => return Err(CliError::Usage(format!("unknown output format '{format}'"))),
+                }
+            }
+            _ => return Err(CliError::Usage(format!("unknown option '{flag}'"))),
+        }
+    }
+    options.width = width.ok_or_else(|| CliError::Usage("--width is required".into()))?;
+    options.height = height.ok_or_else(|| CliError::Usage("--height is required".into()))?;
+    Ok(Some(options))
+}
+
+fn parse_size(flag: &str, value: &str) -> Result<u32, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError::Usage(format!("{flag} expects a number, found '{value}'")))
+}
+
+/// Применяет все команды и выводит итоговый дисплей в out.
+/// Команды читаются из --script или, если его нет, из stdin
+pub fn run(options: &Options, stdin: impl BufRead, out: &mut impl Write) -> Result<(), CliError> {
+    let palette = match &options.palette {
+        Some(path) => Palette::load(path).map_err(CliError::Palette)?,
+        None => Palette::default(),
+    };
+    let default_colour = match &options.default_colour {
+        Some(colour) => {
+            parse_colour(colour, &palette).ok_or_else(|| CliError::UnknownColour(colour.clone()))?
+        }
+        None => palette
+            .iter()
+            .next()
+            .map(|entry| entry.index)
+            .ok_or_else(|| CliError::UnknownColour("palette is empty".into()))?,
+    };
+    let mut display =
+        create_display_with_palette(options.width, options.height, default_colour, palette);
+
+    // Строки применяются по мере чтения, ввод не собирается целиком
+    match &options.script {
+        Some(path) => apply_lines(&mut display, BufReader::new(File::open(path)?).lines())?,
+        None => apply_lines(&mut display, stdin.lines())?,
+    }
+
+    if !options.quiet {
+        write_output(&display, options.output_format, out)?;
+    }
+    Ok(())
+}
+
+/// Цвет номером или именем из палитры
+pub fn parse_colour(input: &str, palette: &Palette) -> Option<u8> {
+    match input.parse() {
+        Ok(index) => palette.contains(index).then_some(index),
+        Err(_) => palette.index_by_name(input),
+    }
+}
+
+fn apply_lines(
+    display: &mut Display,
+    lines: impl Iterator<Item = io::Result<String>>,
+) -> Result<(), CliError> {
+    // Блок repeat или macro может занимать несколько строк: они копятся,
+    // пока скобки не закроются, и применяются вместе
+    let mut chunk = String::new();
+    let mut first_line = 0;
+    let mut depth = 0;
+    for (index, line) in lines.enumerate() {
+        let line = line?;
+        let code = line.split('#').next().unwrap_or_default();
+        if chunk.is_empty() {
+            if code.trim().is_empty() {
+                continue;
+            }
+            first_line = index;
+        }
+        chunk.push_str(code);
+        chunk.push('\n');
+        depth += script::block_balance(code);
+        if depth <= 0 {
+            apply_chunk(display, &chunk, first_line)?;
+            chunk.clear();
+            depth = 0;
+        }
+    }
+    // Незакрытый блок всё равно разбирается, чтобы ошибка указала на него
+    if !chunk.is_empty() {
+        apply_chunk(display, &chunk, first_line)?;
+    }
+    Ok(())
+}
+
+// first_line - номер первой строки куска с нуля
+fn apply_chunk(display: &mut Display, chunk: &str, first_line: usize) -> Result<(), CliError> {
+    let parse_error = |err: ParseError| {
+        CliError::Parse(ParseError {
+            line: first_line + err.line,
+            ..err
+        })
+    };
+    // Номер строки куска (с единицы) для каждого числа потока: по смещению
+    // команды с ошибкой находится строка, на которой она записана
+    let (result, lines): (_, Vec<usize>) = if chunk
+        .trim_start()
+        .starts_with(|c: char| c.is_ascii_digit())
+    {
+        let stream = script::parse_numbers(chunk).map_err(parse_error)?;
+        let lines = chunk
+            .lines()
+            .enumerate()
+            .flat_map(|(index, line)| iter::repeat_n(index + 1, line.split_whitespace().count()))
+            .collect();
+        (process_commands(display, stream), lines)
+    } else {
+        let parsed = script::parse_with_lines(chunk, display.palette()).map_err(parse_error)?;
+        let lines = parsed
+            .iter()
+            .flat_map(|&(line, command)| iter::repeat_n(line, command.encoded_len()))
+            .collect();
+        let commands: Vec<_> = parsed.into_iter().map(|(_, command)| command).collect();
+        (execute_commands(display, &commands), lines)
+    };
+    result.map_err(|error| CliError::Display {
+        line: first_line + lines.get(error.location().offset).copied().unwrap_or(1),
+        error,
+    })
+}
+
+fn write_output(
+    display: &Display,
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let renderer: Box<dyn Renderer> = match format {
+        OutputFormat::Emoji => Box::new(EmojiRenderer),
+        OutputFormat::Ansi => Box::new(AnsiRenderer),
+        OutputFormat::Ascii => Box::new(AsciiRenderer::new()),
+        OutputFormat::HalfBlock => Box::new(HalfBlockRenderer),
+        OutputFormat::Svg => {
+            let svg = display.flatten().to_svg(16, display.palette());
+            out.write_all(svg.as_bytes())?;
+            return Ok(());
+        }
+        OutputFormat::Ppm => {
+            return display
+                .flatten()
+                .write_ppm(out, display.palette(), NetpbmFormat::Plain)
+                .map_err(|err| CliError::Io(io::Error::other(err)));
+        }
+    };
+    display
+        .flatten()
+        .render(renderer.as_ref(), display.palette(), out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn run_with(line: &str, input: &str) -> Result<String, CliError> {
+        let options = parse_args(args(line))?.unwrap();
+        let mut out = Vec::new();
+        run(&options, input.as_bytes(), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(args(
+            "--width 4 --height=3 --default-colour blue --output-format ascii -q",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!((options.width, options.height), (4, 3));
+        assert_eq!(options.default_colour.as_deref(), Some("blue"));
+        assert_eq!(options.output_format, OutputFormat::Ascii);
+        assert!(options.quiet);
+        assert_eq!(parse_args(args("--help")).unwrap(), None);
+
+        for bad in [
+            "--height 3",
+            "--width x --height 3",
+            "--width 3 --height 3 --output-format png",
+            "--width 3 --height 3 --frobnicate",
+            "--width",
+        ] {
+            let err = parse_args(args(bad)).unwrap_err();
+            assert_eq!(err.exit_code(), EXIT_USAGE, "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_batch_run() {
+        let out = run_with(
+            "--width 3 --height 2 --output-format ascii",
+            "1 1 1 2 3\n# комментарий\n\nPAINT green\n",
+        )
+        .unwrap();
+        assert_eq!(out, "111\n121\n");
+        let out = run_with("--width 2 --height 1 --default-colour 3 --quiet", "2 1").unwrap();
+        assert_eq!(out, "");
+        let out = run_with("--width 1 --height 1 --output-format ppm", "").unwrap();
+        assert_eq!(out, "P3\n1 1\n255\n255 0 0\n");
+    }
+
+    #[test]
+    fn test_multiline_blocks() {
+        let script = "\
+macro dot x y colour {
+  MOVE $x $y   # комментарий внутри блока
+
+  PAINT $colour
+}
+repeat 2 {
+  PAINTNEXT green
+}
+CALL dot 2 1 blue
+";
+        let out = run_with("--width 3 --height 2 --output-format ascii", script).unwrap();
+        assert_eq!(out, "221\n113\n");
+
+        let err =
+            run_with("--width 3 --height 2", "MOVE 1 1\nrepeat 2 {\n  PAINT 1\n").unwrap_err();
+        assert_eq!(err.exit_code(), EXIT_PARSE);
+        assert_eq!(err.to_string(), "line 2, column 1: block is never closed");
+        let err = run_with("--width 3 --height 2", "repeat 2 {\n  PAINT pink\n}").unwrap_err();
+        assert_eq!(err.to_string(), "line 2, column 9: unknown colour 'pink'");
+
+        // Ошибка дисплея на третьей строке блока указывает на эту строку
+        let script = "MOVE 0 0\nrepeat 2 {\n  PAINT 1\n  MOVEBY 1 0\n  MOVE 7 7\n}";
+        let err = run_with("--width 3 --height 2", script).unwrap_err();
+        assert_eq!(err.exit_code(), EXIT_BOUNDS);
+        assert!(err.to_string().starts_with("line 5: "), "{err}");
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let code = |line: &str, input: &str| run_with(line, input).unwrap_err().exit_code();
+        let size = "--width 3 --height 3";
+        assert_eq!(code(size, "MOVE 1 1\nJUMP 1"), EXIT_PARSE);
+        assert_eq!(code(size, "1 x"), EXIT_PARSE);
+        assert_eq!(code(size, "1 3 3"), EXIT_BOUNDS);
+        assert_eq!(code(size, "2 9"), EXIT_COLOUR);
+        assert_eq!(
+            code(&format!("{size} --default-colour pink"), ""),
+            EXIT_COLOUR
+        );
+        assert_eq!(code(size, "42"), EXIT_COMMAND);
+        assert_eq!(
+            code(&format!("{size} --script /nonexistent/script.txt"), ""),
+            EXIT_IO
+        );
+
+        let err = run_with(size, "MOVE 1 1\nPAINT pink").unwrap_err();
+        assert_eq!(err.to_string(), "line 2, column 7: unknown colour 'pink'");
+        let err = run_with(size, "\n1 5 5").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: command #0 at offset 0: move to (5,5) outside 3x3"
+        );
+    }
+}
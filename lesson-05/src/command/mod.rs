@@ -0,0 +1,468 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::cursor::{zigzag_decode, zigzag_encode};
+use crate::error::{DisplayError, Location};
+use crate::matrix::Transform;
+
+pub const OP_MOVE: u64 = 1;
+pub const OP_PAINT: u64 = 2;
+pub const OP_LINE: u64 = 3;
+pub const OP_RECT: u64 = 4;
+pub const OP_FILL_RECT: u64 = 5;
+pub const OP_CIRCLE: u64 = 6;
+pub const OP_FILL: u64 = 7;
+pub const OP_MOVE_BY: u64 = 8;
+pub const OP_PAINT_NEXT: u64 = 9;
+pub const OP_SELECT_LAYER: u64 = 10;
+pub const OP_COPY: u64 = 11;
+pub const OP_STAMP: u64 = 12;
+pub const OP_STAMP_MASKED: u64 = 13;
+pub const OP_ROTATE_90: u64 = 14;
+pub const OP_ROTATE_180: u64 = 15;
+pub const OP_ROTATE_270: u64 = 16;
+pub const OP_FLIP_HORIZONTAL: u64 = 17;
+pub const OP_FLIP_VERTICAL: u64 = 18;
+pub const OP_TRANSPOSE: u64 = 19;
+pub const OP_RESIZE: u64 = 20;
+pub const OP_REPEAT: u64 = 21;
+pub const OP_END: u64 = 22;
+pub const OP_DEFINE: u64 = 23;
+pub const OP_CALL: u64 = 24;
+pub const OP_PARAMS: u64 = 25;
+
+/// Больше параметров у макроса быть не может
+pub const MAX_MACRO_PARAMS: usize = 8;
+/// Аргументы вызова макроса. Для serde - просто массив аргументов
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "Vec<u64>", into = "Vec<u64>")
+)]
+pub struct MacroArgs {
+    len: u8,
+    values: [u64; MAX_MACRO_PARAMS],
+}
+
+impl MacroArgs {
+    /// None, если аргументов больше MAX_MACRO_PARAMS
+    pub fn new(args: &[u64]) -> Option<Self> {
+        if args.len() > MAX_MACRO_PARAMS {
+            return None;
+        }
+        let mut values = [0; MAX_MACRO_PARAMS];
+        values[..args.len()].copy_from_slice(args);
+        Some(Self {
+            len: args.len() as u8,
+            values,
+        })
+    }
+
+    pub fn as_slice(&self) -> &[u64] {
+        &self.values[..self.len as usize]
+    }
+}
+
+/// Аргументов больше, чем MAX_MACRO_PARAMS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TooManyArgs {
+    pub found: usize,
+}
+
+impl fmt::Display for TooManyArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "macro call has {} arguments, at most {MAX_MACRO_PARAMS} allowed",
+            self.found
+        )
+    }
+}
+
+impl Error for TooManyArgs {}
+
+impl TryFrom<Vec<u64>> for MacroArgs {
+    type Error = TooManyArgs;
+
+    fn try_from(args: Vec<u64>) -> Result<Self, Self::Error> {
+        MacroArgs::new(&args).ok_or(TooManyArgs { found: args.len() })
+    }
+}
+
+impl From<MacroArgs> for Vec<u64> {
+    fn from(args: MacroArgs) -> Self {
+        args.as_slice().to_vec()
+    }
+}
+
+/// Разобранная команда дисплея. Аргументы хранятся как есть,
+/// проверка границ и цвета выполняется при применении к дисплею
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Command {
+    /// 1 x y
+    MoveTo { x: u64, y: u64 },
+    /// 2 colour
+    Paint { colour: u64 },
+    /// 3 x y colour - отрезок от курсора до (x, y), курсор переходит в конец отрезка
+    LineTo { x: u64, y: u64, colour: u64 },
+    /// 4 x y colour - контур прямоугольника с углами в курсоре и (x, y)
+    Rect { x: u64, y: u64, colour: u64 },
+    /// 5 x y colour - закрашенный прямоугольник с углами в курсоре и (x, y)
+    FillRect { x: u64, y: u64, colour: u64 },
+    /// 6 radius colour - окружность с центром в курсоре, обрезается по краям дисплея
+    Circle { radius: u64, colour: u64 },
+    /// 7 connectivity colour - заливка области под курсором, connectivity равно 4 или 8
+    Fill { connectivity: u64, colour: u64 },
+    /// 8 dx dy - сдвинуть курсор, смещения закодированы zigzag (0, -1, 1, -2... -> 0, 1, 2, 3...)
+    MoveBy { dx: i64, dy: i64 },
+    /// 9 colour - перекрасить пиксель и сдвинуть курсор на шаг вправо
+    PaintNext { colour: u64 },
+    /// 10 layer - сделать слой активным
+    SelectLayer { layer: u64 },
+    /// 11 width height - запомнить область активного слоя от курсора как новый спрайт
+    Copy { width: u64, height: u64 },
+    /// 12 sprite - нарисовать спрайт, левый верхний угол в курсоре
+    Stamp { sprite: u64 },
+    /// 13 sprite transparent - нарисовать спрайт, пропуская пиксели цвета transparent
+    StampMasked { sprite: u64, transparent: u64 },
+    /// 14-19 - повернуть или отразить все слои дисплея, курсор остаётся на своём пикселе
+    Transform { transform: Transform },
+    /// 20 width height - масштабировать все слои дисплея до нового размера
+    Resize { width: u64, height: u64 },
+    /// 21 count - повторить count раз команды до парной END
+    Repeat { count: u64 },
+    /// 22 - конец блока REPEAT или MACRO
+    End,
+    /// 23 id params - макрос id с params параметрами, тело до парной END
+    Define { id: u64, params: u64 },
+    /// 24 id argc args... - вызвать макрос id с argc аргументами
+    Call { id: u64, args: MacroArgs },
+    /// 25 mask - в следующей команде тела макроса аргумент номер i (с нуля), для
+    /// которого в mask стоит бит i, - это номер параметра, а не само значение
+    Params { mask: u64 },
+}
+
+impl Command {
+    pub fn opcode(&self) -> u64 {
+        match self {
+            Command::MoveTo { .. } => OP_MOVE,
+            Command::Paint { .. } => OP_PAINT,
+            Command::LineTo { .. } => OP_LINE,
+            Command::Rect { .. } => OP_RECT,
+            Command::FillRect { .. } => OP_FILL_RECT,
+            Command::Circle { .. } => OP_CIRCLE,
+            Command::Fill { .. } => OP_FILL,
+            Command::MoveBy { .. } => OP_MOVE_BY,
+            Command::PaintNext { .. } => OP_PAINT_NEXT,
+            Command::SelectLayer { .. } => OP_SELECT_LAYER,
+            Command::Copy { .. } => OP_COPY,
+            Command::Stamp { .. } => OP_STAMP,
+            Command::StampMasked { .. } => OP_STAMP_MASKED,
+            Command::Transform { transform } => match transform {
+                Transform::Rotate90 => OP_ROTATE_90,
+                Transform::Rotate180 => OP_ROTATE_180,
+                Transform::Rotate270 => OP_ROTATE_270,
+                Transform::FlipHorizontal => OP_FLIP_HORIZONTAL,
+                Transform::FlipVertical => OP_FLIP_VERTICAL,
+                Transform::Transpose => OP_TRANSPOSE,
+            },
+            Command::Resize { .. } => OP_RESIZE,
+            Command::Repeat { .. } => OP_REPEAT,
+            Command::End => OP_END,
+            Command::Define { .. } => OP_DEFINE,
+            Command::Call { .. } => OP_CALL,
+            Command::Params { .. } => OP_PARAMS,
+        }
+    }
+
+    /// Длина команды в потоке вместе с опкодом
+    pub fn encoded_len(&self) -> usize {
+        let args = match self {
+            Command::Call { args, .. } => args.as_slice().len(),
+            _ => 0,
+        };
+        1 + arity(self.opcode()).unwrap_or(0) + args
+    }
+
+    pub fn encode(&self, out: &mut Vec<u64>) {
+        out.push(self.opcode());
+        match *self {
+            Command::MoveTo { x, y } => out.extend([x, y]),
+            Command::Paint { colour } | Command::PaintNext { colour } => out.push(colour),
+            Command::SelectLayer { layer } => out.push(layer),
+            Command::Copy { width, height } => out.extend([width, height]),
+            Command::Stamp { sprite } => out.push(sprite),
+            Command::StampMasked {
+                sprite,
+                transparent,
+            } => out.extend([sprite, transparent]),
+            Command::Transform { .. } | Command::End => {}
+            Command::Repeat { count } => out.push(count),
+            Command::Params { mask } => out.push(mask),
+            Command::Define { id, params } => out.extend([id, params]),
+            Command::Call { id, args } => {
+                out.extend([id, args.as_slice().len() as u64]);
+                out.extend_from_slice(args.as_slice());
+            }
+            Command::Resize { width, height } => out.extend([width, height]),
+            Command::LineTo { x, y, colour }
+            | Command::Rect { x, y, colour }
+            | Command::FillRect { x, y, colour } => out.extend([x, y, colour]),
+            Command::Circle { radius, colour } => out.extend([radius, colour]),
+            Command::Fill {
+                connectivity,
+                colour,
+            } => out.extend([connectivity, colour]),
+            Command::MoveBy { dx, dy } => out.extend([zigzag_encode(dx), zigzag_encode(dy)]),
+        }
+    }
+
+    /// Разбирает команду с опкодом `at.opcode`, забирая её аргументы из args
+    pub fn decode(
+        at: Location,
+        args: &mut impl Iterator<Item = u64>,
+    ) -> Result<Command, DisplayError> {
+        match at.opcode {
+            OP_MOVE => {
+                let [x, y] = arguments(args, at)?;
+                Ok(Command::MoveTo { x, y })
+            }
+            OP_PAINT => {
+                let [colour] = arguments(args, at)?;
+                Ok(Command::Paint { colour })
+            }
+            OP_LINE => {
+                let [x, y, colour] = arguments(args, at)?;
+                Ok(Command::LineTo { x, y, colour })
+            }
+            OP_RECT => {
+                let [x, y, colour] = arguments(args, at)?;
+                Ok(Command::Rect { x, y, colour })
+            }
+            OP_FILL_RECT => {
+                let [x, y, colour] = arguments(args, at)?;
+                Ok(Command::FillRect { x, y, colour })
+            }
+            OP_CIRCLE => {
+                let [radius, colour] = arguments(args, at)?;
+                Ok(Command::Circle { radius, colour })
+            }
+            OP_FILL => {
+                let [connectivity, colour] = arguments(args, at)?;
+                Ok(Command::Fill {
+                    connectivity,
+                    colour,
+                })
+            }
+            OP_MOVE_BY => {
+                let [dx, dy] = arguments(args, at)?;
+                Ok(Command::MoveBy {
+                    dx: zigzag_decode(dx),
+                    dy: zigzag_decode(dy),
+                })
+            }
+            OP_PAINT_NEXT => {
+                let [colour] = arguments(args, at)?;
+                Ok(Command::PaintNext { colour })
+            }
+            OP_SELECT_LAYER => {
+                let [layer] = arguments(args, at)?;
+                Ok(Command::SelectLayer { layer })
+            }
+            OP_COPY => {
+                let [width, height] = arguments(args, at)?;
+                Ok(Command::Copy { width, height })
+            }
+            OP_STAMP => {
+                let [sprite] = arguments(args, at)?;
+                Ok(Command::Stamp { sprite })
+            }
+            OP_STAMP_MASKED => {
+                let [sprite, transparent] = arguments(args, at)?;
+                Ok(Command::StampMasked {
+                    sprite,
+                    transparent,
+                })
+            }
+            OP_ROTATE_90 => Ok(Command::Transform {
+                transform: Transform::Rotate90,
+            }),
+            OP_ROTATE_180 => Ok(Command::Transform {
+                transform: Transform::Rotate180,
+            }),
+            OP_ROTATE_270 => Ok(Command::Transform {
+                transform: Transform::Rotate270,
+            }),
+            OP_FLIP_HORIZONTAL => Ok(Command::Transform {
+                transform: Transform::FlipHorizontal,
+            }),
+            OP_FLIP_VERTICAL => Ok(Command::Transform {
+                transform: Transform::FlipVertical,
+            }),
+            OP_TRANSPOSE => Ok(Command::Transform {
+                transform: Transform::Transpose,
+            }),
+            OP_RESIZE => {
+                let [width, height] = arguments(args, at)?;
+                Ok(Command::Resize { width, height })
+            }
+            OP_REPEAT => {
+                let [count] = arguments(args, at)?;
+                Ok(Command::Repeat { count })
+            }
+            OP_END => Ok(Command::End),
+            OP_DEFINE => {
+                let [id, params] = arguments(args, at)?;
+                Ok(Command::Define { id, params })
+            }
+            OP_CALL => {
+                let [id, argc] = arguments(args, at)?;
+                let count = usize::try_from(argc)
+                    .ok()
+                    .filter(|&count| count <= MAX_MACRO_PARAMS)
+                    .ok_or(DisplayError::InvalidArgument {
+                        at,
+                        name: "argument count",
+                        value: argc,
+                    })?;
+                let mut values = [0; MAX_MACRO_PARAMS];
+                for (found, value) in values[..count].iter_mut().enumerate() {
+                    *value = args.next().ok_or(DisplayError::MissingArguments {
+                        at,
+                        expected: 2 + count,
+                        found: 2 + found,
+                    })?;
+                }
+                let args = MacroArgs {
+                    len: count as u8,
+                    values,
+                };
+                Ok(Command::Call { id, args })
+            }
+            OP_PARAMS => {
+                let [mask] = arguments(args, at)?;
+                Ok(Command::Params { mask })
+            }
+            _ => Err(DisplayError::UnknownOpcode { at }),
+        }
+    }
+}
+
+/// Число аргументов опкода, None для неизвестных опкодов.
+/// У CALL после двух аргументов идут ещё argc аргументов вызова
+pub fn arity(opcode: u64) -> Option<usize> {
+    match opcode {
+        OP_END => Some(0),
+        OP_REPEAT | OP_PARAMS => Some(1),
+        OP_DEFINE | OP_CALL => Some(2),
+        OP_MOVE | OP_MOVE_BY => Some(2),
+        OP_PAINT | OP_PAINT_NEXT | OP_SELECT_LAYER | OP_STAMP => Some(1),
+        OP_LINE | OP_RECT | OP_FILL_RECT => Some(3),
+        OP_CIRCLE | OP_FILL | OP_COPY | OP_STAMP_MASKED | OP_RESIZE => Some(2),
+        OP_ROTATE_90..=OP_TRANSPOSE => Some(0),
+        _ => None,
+    }
+}
+
+/// Мнемоника опкода в текстовом языке скриптов
+pub fn mnemonic(opcode: u64) -> Option<&'static str> {
+    match opcode {
+        OP_MOVE => Some("MOVE"),
+        OP_PAINT => Some("PAINT"),
+        OP_LINE => Some("LINE"),
+        OP_RECT => Some("RECT"),
+        OP_FILL_RECT => Some("FILLRECT"),
+        OP_CIRCLE => Some("CIRCLE"),
+        OP_FILL => Some("FILL"),
+        OP_MOVE_BY => Some("MOVEBY"),
+        OP_PAINT_NEXT => Some("PAINTNEXT"),
+        OP_SELECT_LAYER => Some("LAYER"),
+        OP_COPY => Some("COPY"),
+        OP_STAMP => Some("STAMP"),
+        OP_STAMP_MASKED => Some("STAMPMASKED"),
+        OP_ROTATE_90 => Some("ROTATE90"),
+        OP_ROTATE_180 => Some("ROTATE180"),
+        OP_ROTATE_270 => Some("ROTATE270"),
+        OP_FLIP_HORIZONTAL => Some("FLIPH"),
+        OP_FLIP_VERTICAL => Some("FLIPV"),
+        OP_TRANSPOSE => Some("TRANSPOSE"),
+        OP_RESIZE => Some("RESIZE"),
+        OP_REPEAT => Some("REPEAT"),
+        OP_END => Some("END"),
+        OP_DEFINE => Some("MACRO"),
+        OP_CALL => Some("CALL"),
+        OP_PARAMS => Some("PARAMS"),
+        _ => None,
+    }
+}
+
+/// Собирает команды обратно в числовой поток для process_commands
+pub fn encode(commands: &[Command]) -> Vec<u64> {
+    let mut out = Vec::new();
+    for command in commands {
+        command.encode(&mut out);
+    }
+    out
+}
+
+/// Разбирает числовой поток по мере чтения, не собирая его целиком.
+/// После первой ошибки больше ничего не возвращает
+pub struct Decoder<I> {
+    input: I,
+    offset: usize,
+    command: usize,
+    failed: bool,
+}
+
+impl<I: Iterator<Item = u64>> Iterator for Decoder<I> {
+    type Item = Result<(Location, Command), DisplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let at = Location {
+            command: self.command,
+            offset: self.offset,
+            opcode: self.input.next()?,
+        };
+        match Command::decode(at, &mut self.input) {
+            Ok(command) => {
+                self.offset += command.encoded_len();
+                self.command += 1;
+                Some(Ok((at, command)))
+            }
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+pub fn decode<I: IntoIterator<Item = u64>>(input: I) -> Decoder<I::IntoIter> {
+    Decoder {
+        input: input.into_iter(),
+        offset: 0,
+        command: 0,
+        failed: false,
+    }
+}
+
+// Берёт N аргументов команды, если поток не оборвался раньше
+fn arguments<const N: usize>(
+    args: &mut impl Iterator<Item = u64>,
+    at: Location,
+) -> Result<[u64; N], DisplayError> {
+    let mut values = [0; N];
+    for (found, value) in values.iter_mut().enumerate() {
+        *value = args.next().ok_or(DisplayError::MissingArguments {
+            at,
+            expected: N,
+            found,
+        })?;
+    }
+    Ok(values)
+}
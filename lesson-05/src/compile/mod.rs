@@ -0,0 +1,620 @@
+// Компиляция целевой матрицы в поток команд. Поток переводит дисплей с
+// матрицей start в матрицу target и рассчитан на курсор в (0, 0), как сразу
+// после create_display, политику края EdgePolicy::Error и начало координат
+// Origin::TopLeft.
+//
+// Длина потока считается в числах u64. Сначала жадно выбираются крупные фигуры:
+// FILL связной области, FILLRECT и RECT по рамке неправильных пикселей одного
+// цвета, LINE по длинным одноцветным участкам строк и столбцов. Фигура берётся,
+// только если с ней весь поток становится короче. Оставшиеся пиксели рисуются
+// построчно или по столбцам, что короче: внутри линии динамическое
+// программирование выбирает между PAINT, PAINTNEXT, LINE и переходами MOVE.
+// Правильные пиксели не перекрашиваются, если только их не выгоднее закрасить
+// тем же цветом по пути. Курсор, оставленный фигурой или предыдущей линией,
+// используется без перехода. MOVE и MOVEBY одной длины, поэтому переходы
+// всегда абсолютные.
+use std::error::Error;
+use std::fmt;
+
+use std::collections::HashMap;
+
+use crate::command::{self, Command};
+use crate::draw;
+use crate::matrix::{Connectivity, Matrix};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    /// Размеры матриц (ширина, высота) не совпадают
+    SizeMismatch {
+        start: (usize, usize),
+        target: (usize, usize),
+    },
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::SizeMismatch { start, target } => write!(
+                f,
+                "cannot compile {}x{} matrix into {}x{}",
+                start.0, start.1, target.0, target.1
+            ),
+        }
+    }
+}
+
+impl Error for CompileError {}
+
+/// Кратчайший найденный поток чисел для process_commands
+pub fn compile(start: &Matrix, target: &Matrix) -> Result<Vec<u64>, CompileError> {
+    Ok(command::encode(&compile_commands(start, target)?))
+}
+
+/// То же, что compile, но в виде разобранных команд
+pub fn compile_commands(start: &Matrix, target: &Matrix) -> Result<Vec<Command>, CompileError> {
+    let size = (start.width(), start.height());
+    if size != (target.width(), target.height()) {
+        return Err(CompileError::SizeMismatch {
+            start: size,
+            target: (target.width(), target.height()),
+        });
+    }
+    let mut commands = Vec::new();
+    let mut current = start.clone();
+    let mut cursor = (0, 0);
+    let mut rest = pixels(&current, target, cursor);
+    loop {
+        let mut best = None;
+        let mut best_length = length(&rest);
+        for shape in shapes(&current, target, cursor) {
+            let mut after = current.clone();
+            let moved = shape
+                .iter()
+                .fold(cursor, |cursor, command| apply(&mut after, cursor, command));
+            let tail = pixels(&after, target, moved);
+            let total = length(&shape) + length(&tail);
+            if total < best_length {
+                best_length = total;
+                best = Some((shape, after, moved, tail));
+            }
+        }
+        let Some((shape, after, moved, tail)) = best else {
+            break;
+        };
+        commands.extend(shape);
+        (current, cursor, rest) = (after, moved, tail);
+    }
+    commands.extend(rest);
+    Ok(commands)
+}
+
+// Сколько чисел займут команды в потоке
+fn length(commands: &[Command]) -> usize {
+    command::encode(commands).len()
+}
+
+// Куда команда переводит курсор
+fn moved(cursor: (u64, u64), command: &Command) -> (u64, u64) {
+    match *command {
+        Command::MoveTo { x, y } | Command::LineTo { x, y, .. } => (x, y),
+        Command::PaintNext { .. } => (cursor.0 + 1, cursor.1),
+        _ => cursor,
+    }
+}
+
+// Рисует фигуру на матрице так же, как дисплей, и возвращает новый курсор
+fn apply(matrix: &mut Matrix, cursor: (u64, u64), command: &Command) -> (u64, u64) {
+    let from = (cursor.0 as i64, cursor.1 as i64);
+    let mut plot =
+        |x: i64, y: i64, colour: u64| matrix.set_colour(x as u64, y as u64, colour as u8);
+    match *command {
+        Command::LineTo { x, y, colour } => {
+            draw::line(from, (x as i64, y as i64), |x, y| plot(x, y, colour))
+        }
+        Command::Rect { x, y, colour } => {
+            draw::outlined_rect(from, (x as i64, y as i64), |x, y| plot(x, y, colour))
+        }
+        Command::FillRect { x, y, colour } => {
+            draw::filled_rect(from, (x as i64, y as i64), |x, y| plot(x, y, colour))
+        }
+        Command::Fill { colour, .. } => {
+            matrix.flood_fill(cursor.0, cursor.1, colour as u8, Connectivity::Four)
+        }
+        _ => {}
+    }
+    moved(cursor, command)
+}
+
+// Сколько заливок и отрезков каждого направления пробовать за шаг
+const FILL_CANDIDATES: usize = 8;
+const LINE_CANDIDATES: usize = 4;
+
+// Фигуры-кандидаты, каждая с переходом в начальную точку, если курсор не там
+fn shapes(current: &Matrix, target: &Matrix, cursor: (u64, u64)) -> Vec<Vec<Command>> {
+    let placed = |at: (u64, u64), command: Command| {
+        if at == cursor {
+            vec![command]
+        } else {
+            vec![Command::MoveTo { x: at.0, y: at.1 }, command]
+        }
+    };
+    let mut shapes = Vec::new();
+
+    // Рамки неправильных пикселей каждого цвета: (левый, верхний, правый, нижний)
+    let mut boxes: [Option<(u64, u64, u64, u64)>; 256] = [None; 256];
+    for (x, y, colour) in target.pixels() {
+        if current.colour(x, y) != colour {
+            let bounds = boxes[colour as usize].get_or_insert((x, y, x, y));
+            *bounds = (
+                bounds.0.min(x),
+                bounds.1.min(y),
+                bounds.2.max(x),
+                bounds.3.max(y),
+            );
+        }
+    }
+    for (colour, bounds) in boxes.iter().enumerate() {
+        let Some((left, top, right, bottom)) = *bounds else {
+            continue;
+        };
+        // Начинаем из угла, где уже стоит курсор, иначе из левого верхнего
+        let corners = [
+            ((left, top), (right, bottom)),
+            ((right, bottom), (left, top)),
+            ((right, top), (left, bottom)),
+            ((left, bottom), (right, top)),
+        ];
+        let (from, (x, y)) = corners
+            .into_iter()
+            .find(|&(corner, _)| corner == cursor)
+            .unwrap_or(corners[0]);
+        let colour = colour as u64;
+        shapes.push(placed(from, Command::FillRect { x, y, colour }));
+        shapes.push(placed(from, Command::Rect { x, y, colour }));
+    }
+
+    // Заливки связных областей, где больше всего пикселей хотят одного цвета
+    let (labels, count) = components(current);
+    let mut wanted: HashMap<(usize, u8), (usize, (u64, u64))> = HashMap::new();
+    for (x, y, colour) in target.pixels() {
+        if current.colour(x, y) != colour {
+            let label = labels[y as usize * target.width() + x as usize];
+            wanted.entry((label, colour)).or_insert((0, (x, y))).0 += 1;
+        }
+    }
+    let mut fills: Vec<_> = wanted
+        .into_iter()
+        .filter(|&(_, (wrong, _))| wrong >= 2)
+        .collect();
+    fills.sort_by_key(|&((label, colour), (wrong, _))| (usize::MAX - wrong, label, colour));
+    let mut taken = vec![false; count];
+    let mut fill_count = 0;
+    let cursor_label = labels.get(cursor.1 as usize * target.width() + cursor.0 as usize);
+    for ((label, colour), (_, pixel)) in fills {
+        if taken[label] {
+            continue;
+        }
+        taken[label] = true;
+        let from = if cursor_label == Some(&label) {
+            cursor
+        } else {
+            pixel
+        };
+        let connectivity = 4;
+        let colour = colour as u64;
+        shapes.push(placed(
+            from,
+            Command::Fill {
+                connectivity,
+                colour,
+            },
+        ));
+        fill_count += 1;
+        if fill_count == FILL_CANDIDATES {
+            break;
+        }
+    }
+
+    // Самые длинные одноцветные участки строк и столбцов с неправильными пикселями
+    for axis in [Axis::Row, Axis::Column] {
+        let mut runs = Vec::new();
+        let (lanes, len) = axis.size(target);
+        for lane in 0..lanes {
+            let mut from = 0;
+            while from < len {
+                let colour = colour_at(target, axis.point(lane, from));
+                let mut to = from;
+                let mut wrong = 0;
+                while to < len && colour_at(target, axis.point(lane, to)) == colour {
+                    if colour_at(current, axis.point(lane, to)) != colour {
+                        wrong += 1;
+                    }
+                    to += 1;
+                }
+                if wrong >= 2 {
+                    runs.push((wrong, lane, from, to - 1, colour));
+                }
+                from = to;
+            }
+        }
+        runs.sort_by_key(|&(wrong, lane, from, ..)| (usize::MAX - wrong, lane, from));
+        for (_, lane, first, last, colour) in runs.into_iter().take(LINE_CANDIDATES) {
+            let (mut from, mut to) = (axis.point(lane, first), axis.point(lane, last));
+            if to == cursor {
+                (from, to) = (to, from);
+            }
+            let colour = colour as u64;
+            let (x, y) = to;
+            shapes.push(placed(from, Command::LineTo { x, y, colour }));
+        }
+    }
+    shapes
+}
+
+// Номера связных (по четырём соседям) одноцветных областей для каждого пикселя
+// и число областей
+fn components(matrix: &Matrix) -> (Vec<usize>, usize) {
+    let (width, height) = (matrix.width(), matrix.height());
+    let mut labels = vec![usize::MAX; width * height];
+    let mut count = 0;
+    let mut stack = Vec::new();
+    for start in 0..labels.len() {
+        if labels[start] != usize::MAX {
+            continue;
+        }
+        let colour = matrix.colour((start % width) as u64, (start / width) as u64);
+        labels[start] = count;
+        stack.push(start);
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            let neighbours = [
+                (x > 0).then(|| index - 1),
+                (x + 1 < width).then(|| index + 1),
+                (y > 0).then(|| index - width),
+                (y + 1 < height).then(|| index + width),
+            ];
+            for next in neighbours.into_iter().flatten() {
+                if labels[next] == usize::MAX
+                    && matrix.colour((next % width) as u64, (next / width) as u64) == colour
+                {
+                    labels[next] = count;
+                    stack.push(next);
+                }
+            }
+        }
+        count += 1;
+    }
+    (labels, count)
+}
+
+// Оставшиеся пиксели построчно или по столбцам, что короче
+fn pixels(current: &Matrix, target: &Matrix, cursor: (u64, u64)) -> Vec<Command> {
+    let rows = lanes(current, target, cursor, Axis::Row);
+    let columns = lanes(current, target, cursor, Axis::Column);
+    if length(&columns) < length(&rows) {
+        columns
+    } else {
+        rows
+    }
+}
+
+// Линии дисплея: строки слева направо или столбцы сверху вниз
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Axis {
+    Row,
+    Column,
+}
+
+impl Axis {
+    // Число линий и длина каждой
+    fn size(self, matrix: &Matrix) -> (u64, u64) {
+        let (width, height) = (matrix.width() as u64, matrix.height() as u64);
+        match self {
+            Axis::Row => (height, width),
+            Axis::Column => (width, height),
+        }
+    }
+
+    // Точка index на линии lane
+    fn point(self, lane: u64, index: u64) -> (u64, u64) {
+        match self {
+            Axis::Row => (index, lane),
+            Axis::Column => (lane, index),
+        }
+    }
+
+    // Линия и место на ней для точки
+    fn locate(self, (x, y): (u64, u64)) -> (u64, u64) {
+        match self {
+            Axis::Row => (y, x),
+            Axis::Column => (x, y),
+        }
+    }
+}
+
+fn colour_at(matrix: &Matrix, (x, y): (u64, u64)) -> u8 {
+    matrix.colour(x, y)
+}
+
+fn lanes(current: &Matrix, target: &Matrix, mut cursor: (u64, u64), axis: Axis) -> Vec<Command> {
+    let (count, len) = axis.size(target);
+    let mut out = Vec::new();
+    for lane in 0..count {
+        let start: Vec<u8> = (0..len)
+            .map(|index| colour_at(current, axis.point(lane, index)))
+            .collect();
+        let goal: Vec<u8> = (0..len)
+            .map(|index| colour_at(target, axis.point(lane, index)))
+            .collect();
+        let (cursor_lane, index) = axis.locate(cursor);
+        let here = (cursor_lane == lane).then_some(index as usize);
+        let from = out.len();
+        compile_lane(&start, &goal, lane, axis, here, &mut out);
+        cursor = out[from..].iter().fold(cursor, moved);
+    }
+    out
+}
+
+const MOVE_COST: u64 = 3;
+const PAINT_COST: u64 = 2;
+const LINE_COST: u64 = 4;
+
+// Состояние после обработки столбцов левее x: курсор стоит в x или где-то ещё
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Away(usize),
+    At(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Step {
+    from: State,
+    commands: [Option<Command>; 2],
+}
+
+struct Table {
+    width: usize,
+    cost: Vec<u64>,
+    steps: Vec<Option<Step>>,
+}
+
+impl Table {
+    fn index(state: State) -> usize {
+        match state {
+            State::Away(x) => 2 * x,
+            State::At(x) => 2 * x + 1,
+        }
+    }
+
+    fn cost(&self, state: State) -> u64 {
+        self.cost[Self::index(state)]
+    }
+
+    fn relax(&mut self, from: State, to: State, cost: u64, commands: [Option<Command>; 2]) {
+        if let State::At(x) = to {
+            if x >= self.width {
+                return;
+            }
+        }
+        let total = self.cost(from).saturating_add(cost);
+        let index = Self::index(to);
+        if total < self.cost[index] {
+            self.cost[index] = total;
+            self.steps[index] = Some(Step { from, commands });
+        }
+    }
+}
+
+// Одна линия; cursor - место курсора на ней, если он стоит на этой линии
+fn compile_lane(
+    start: &[u8],
+    target: &[u8],
+    lane: u64,
+    axis: Axis,
+    cursor: Option<usize>,
+    out: &mut Vec<Command>,
+) {
+    let width = target.len();
+    let mut table = Table {
+        width,
+        cost: vec![u64::MAX; 2 * width + 2],
+        steps: vec![None; 2 * width + 2],
+    };
+    table.cost[Table::index(State::Away(0))] = 0;
+    // Курсор пригоден без перехода, только если левее него ничего не нужно рисовать
+    if let Some(x) = cursor.filter(|&x| start[..x] == target[..x]) {
+        table.cost[Table::index(State::At(x))] = 0;
+    }
+
+    // Конец одноцветного участка целевой строки, начинающегося в x
+    let mut run_end = vec![width; width];
+    for x in (0..width.saturating_sub(1)).rev() {
+        if target[x] == target[x + 1] {
+            run_end[x] = run_end[x + 1];
+        } else {
+            run_end[x] = x + 1;
+        }
+    }
+
+    for x in 0..width {
+        let (at, away) = (State::At(x), State::Away(x));
+        let colour = target[x] as u64;
+        let clean = start[x] == target[x];
+        let (point_x, point_y) = axis.point(lane, x as u64);
+        let move_to = Command::MoveTo {
+            x: point_x,
+            y: point_y,
+        };
+        table.relax(away, at, MOVE_COST, [Some(move_to), None]);
+        if clean {
+            table.relax(away, State::Away(x + 1), 0, [None, None]);
+            table.relax(at, State::Away(x + 1), 0, [None, None]);
+        }
+        if table.cost(at) == u64::MAX {
+            continue;
+        }
+        // PAINTNEXT сдвигает курсор только вправо
+        let paint_next = Command::PaintNext { colour };
+        if axis == Axis::Row {
+            table.relax(at, State::At(x + 1), PAINT_COST, [Some(paint_next), None]);
+        }
+        let paint = Command::Paint { colour };
+        table.relax(at, State::Away(x + 1), PAINT_COST, [Some(paint), None]);
+
+        let end = run_end[x];
+        if end - x >= 2 {
+            let (x, y) = axis.point(lane, end as u64 - 1);
+            let line = Command::LineTo { x, y, colour };
+            table.relax(at, State::Away(end), LINE_COST, [Some(line), None]);
+            // Последний пиксель отрезка перекрашивается ещё раз, чтобы сдвинуть курсор
+            if axis == Axis::Row {
+                let cost = LINE_COST + PAINT_COST;
+                table.relax(at, State::At(end), cost, [Some(line), Some(paint_next)]);
+            }
+        }
+    }
+
+    let mut row = Vec::new();
+    let mut state = State::Away(width);
+    while let Some(step) = table.steps[Table::index(state)] {
+        row.extend(step.commands.iter().rev().flatten().copied());
+        state = step.from;
+    }
+    out.extend(row.into_iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+    use crate::{create_display_from_matrix, process_commands};
+
+    fn check(start: &Matrix, target: &Matrix) -> Vec<u64> {
+        let stream = compile(start, target).unwrap();
+        let mut display = create_display_from_matrix(start.clone(), Palette::default());
+        process_commands(&mut display, stream.clone()).unwrap();
+        crate::assert_matrix_eq!(*display.matrix(), *target);
+        stream
+    }
+
+    #[test]
+    fn test_identical_matrices() {
+        let matrix = Matrix::new(4, 4, 1);
+        assert!(check(&matrix, &matrix).is_empty());
+    }
+
+    #[test]
+    fn test_short_streams() {
+        let start = Matrix::new(5, 5, 1);
+        // Пиксель под курсором
+        let mut target = start.clone();
+        target.set_colour(0, 0, 3);
+        assert_eq!(check(&start, &target), vec![2, 3]);
+
+        // Одиночный пиксель: переход и покраска
+        let mut target = start.clone();
+        target.set_colour(3, 2, 2);
+        assert_eq!(check(&start, &target), vec![1, 3, 2, 2, 2]);
+
+        // Один правильный пиксель между двумя неправильными выгоднее перекрасить
+        let mut target = start.clone();
+        target.set_colour(1, 1, 2);
+        target.set_colour(3, 1, 3);
+        assert_eq!(check(&start, &target), vec![1, 1, 1, 9, 2, 9, 1, 2, 3]);
+
+        // Длинный одноцветный участок рисуется отрезком
+        let mut target = start.clone();
+        for x in 0..5 {
+            target.set_colour(x, 4, 2);
+        }
+        assert_eq!(check(&start, &target), vec![1, 0, 4, 3, 4, 4, 2]);
+    }
+
+    #[test]
+    fn test_shapes() {
+        // Перекраска всего дисплея - одна заливка, короче, чем 5 99 99 2
+        let start = Matrix::new(100, 100, 1);
+        assert_eq!(check(&start, &Matrix::new(100, 100, 2)), vec![7, 4, 2]);
+
+        // Закрашенный прямоугольник посреди дисплея
+        let mut target = start.clone();
+        for y in 20..60 {
+            for x in 10..90 {
+                target.set_colour(x, y, 3);
+            }
+        }
+        assert_eq!(check(&start, &target), vec![1, 10, 20, 5, 89, 59, 3]);
+
+        // Контур прямоугольника
+        let start = Matrix::new(10, 10, 1);
+        let mut target = start.clone();
+        for i in 1..9 {
+            for (x, y) in [(i, 1), (i, 8), (1, i), (8, i)] {
+                target.set_colour(x, y, 2);
+            }
+        }
+        assert_eq!(check(&start, &target), vec![1, 1, 1, 4, 8, 8, 2]);
+    }
+
+    #[test]
+    fn test_vertical_line() {
+        let start = Matrix::new(5, 15, 1);
+        let mut target = start.clone();
+        for y in 2..12 {
+            target.set_colour(3, y, 2);
+        }
+        assert_eq!(check(&start, &target), vec![1, 3, 2, 3, 3, 11, 2]);
+
+        // Курсор остаётся в конце отрезка, и соседний пиксель красится без перехода
+        target.set_colour(4, 11, 3);
+        assert_eq!(
+            check(&start, &target),
+            vec![1, 3, 2, 3, 3, 11, 2, 9, 2, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_pseudo_random_matrices() {
+        let mut seed = 7u64;
+        let mut next = move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as u8 % 3 + 1
+        };
+        for size in [1, 2, 3, 8, 40] {
+            let mut start = Matrix::new(size, size, 1);
+            let mut target = Matrix::new(size, size, 1);
+            for x in 0..size as u64 {
+                for y in 0..size as u64 {
+                    start.set_colour(x, y, next());
+                    target.set_colour(x, y, next());
+                }
+            }
+            let stream = check(&start, &target);
+            // Не длиннее покраски каждого пикселя отдельно
+            assert!(stream.len() <= 5 * (size * size) as usize);
+        }
+    }
+
+    #[test]
+    fn test_non_square() {
+        let start = Matrix::new(6, 2, 1);
+        let mut target = start.clone();
+        for x in 1..6 {
+            target.set_colour(x, 1, 3);
+        }
+        target.set_colour(5, 0, 2);
+        assert_eq!(
+            check(&start, &target),
+            vec![1, 5, 0, 2, 2, 1, 1, 1, 3, 5, 1, 3]
+        );
+        check(&target, &start);
+    }
+
+    #[test]
+    fn test_size_mismatch() {
+        let err = compile(&Matrix::new(2, 2, 1), &Matrix::new(3, 3, 1)).unwrap_err();
+        assert_eq!(err.to_string(), "cannot compile 2x2 matrix into 3x3");
+    }
+}
@@ -0,0 +1,476 @@
+// Управляющие команды потока: REPEAT, MACRO, CALL и END.
+//
+// Блоки верхнего уровня собираются из потока до парной END и только потом
+// выполняются, остальные команды выполняются сразу, как и раньше. Тела макросов
+// хранятся в Display, поэтому макрос из одного вызова process_commands доступен
+// в следующих. Вложенность блоков и вызовов ограничена MAX_DEPTH, а число
+// команд, порождённых повторами и вызовами, - бюджетом дисплея, чтобы поток вроде
+// "REPEAT 10^18 { ... }" не подвешивал программу. Обычные команды потока бюджет
+// не тратят, поток может быть сколь угодно длинным.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::command::Command;
+use crate::cursor::zigzag_encode;
+use crate::store::PixelStore;
+use crate::{Display, DisplayError, Location};
+
+/// Бюджет команд из повторов и тел макросов на один вызов process_commands по умолчанию
+pub const DEFAULT_BUDGET: u64 = 1_000_000;
+/// Наибольшая вложенность блоков REPEAT и вызовов макросов
+pub const MAX_DEPTH: usize = 64;
+
+type Body = Rc<[(Location, Command)]>;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Macro {
+    params: usize,
+    // Тело - часть команд блока, в котором макрос определён
+    body: Body,
+    range: Range<usize>,
+}
+
+// Блок, который ещё собирается из потока
+struct Open {
+    at: Location,
+    header: Command,
+    // Сколько вложенных блоков внутри ещё не закрыто
+    nested: usize,
+    body: Vec<(Location, Command)>,
+}
+
+/// Исполнитель простых команд, которые получаются после раскрытия блоков
+pub(crate) trait Machine {
+    fn macros(&mut self) -> &mut HashMap<u64, Macro>;
+    fn instruction_budget(&self) -> u64;
+    fn execute(&mut self, at: Location, command: Command) -> Result<(), DisplayError>;
+}
+
+impl<S: PixelStore> Machine for Display<S> {
+    fn macros(&mut self) -> &mut HashMap<u64, Macro> {
+        &mut self.macros
+    }
+
+    fn instruction_budget(&self) -> u64 {
+        self.instruction_budget
+    }
+
+    fn execute(&mut self, at: Location, command: Command) -> Result<(), DisplayError> {
+        Display::execute(self, &command, at)
+    }
+}
+
+pub(crate) struct Interpreter<'a, M> {
+    machine: &'a mut M,
+    open: Option<Open>,
+    executed: u64,
+}
+
+impl<'a, M: Machine> Interpreter<'a, M> {
+    pub fn new(machine: &'a mut M) -> Self {
+        Self {
+            machine,
+            open: None,
+            executed: 0,
+        }
+    }
+
+    /// Принимает очередную команду потока
+    pub fn feed(&mut self, at: Location, command: Command) -> Result<(), DisplayError> {
+        let Some(open) = &mut self.open else {
+            return match command {
+                Command::Repeat { .. } | Command::Define { .. } => {
+                    self.open = Some(Open {
+                        at,
+                        header: command,
+                        nested: 0,
+                        body: Vec::new(),
+                    });
+                    Ok(())
+                }
+                _ => self.run(at, command, 0),
+            };
+        };
+        match command {
+            Command::Repeat { .. } | Command::Define { .. } => open.nested += 1,
+            Command::End if open.nested > 0 => open.nested -= 1,
+            Command::End => {
+                let Open {
+                    at: header_at,
+                    header,
+                    body,
+                    ..
+                } = self.open.take().expect("block is open");
+                let body: Body = body.into();
+                let range = 0..body.len();
+                return self.block(header_at, header, &body, range, None, 0);
+            }
+            _ => {}
+        }
+        open.body.push((at, command));
+        Ok(())
+    }
+
+    /// Проверяет, что поток не оборвался посреди блока
+    pub fn finish(self) -> Result<(), DisplayError> {
+        match self.open {
+            Some(open) => Err(DisplayError::UnclosedBlock { at: open.at }),
+            None => Ok(()),
+        }
+    }
+
+    // Выполняет команды body[range]: блоки внутри уже сбалансированы, их границы
+    // ищутся заново. Вложенные блоки получают свою часть того же body без копирования
+    fn body(
+        &mut self,
+        body: &Body,
+        range: Range<usize>,
+        args: Option<&[u64]>,
+        depth: usize,
+    ) -> Result<(), DisplayError> {
+        let mut index = range.start;
+        while index < range.end {
+            let (mut at, mut command) = body[index];
+            index += 1;
+            // PARAMS относится к следующей команде того же блока
+            let mut mask = 0;
+            if let Command::Params { mask: bits } = command {
+                if index == range.end {
+                    return Err(DisplayError::InvalidArgument {
+                        at,
+                        name: "parameters",
+                        value: bits,
+                    });
+                }
+                mask = bits;
+                (at, command) = body[index];
+                index += 1;
+            }
+            let command = substitute(command, at, args, mask)?;
+            if !matches!(command, Command::Repeat { .. } | Command::Define { .. }) {
+                self.run(at, command, depth)?;
+                continue;
+            }
+            let start = index;
+            let mut nested = 0;
+            while !(nested == 0 && body[index].1 == Command::End) {
+                match body[index].1 {
+                    Command::Repeat { .. } | Command::Define { .. } => nested += 1,
+                    Command::End => nested -= 1,
+                    _ => {}
+                }
+                index += 1;
+            }
+            index += 1;
+            self.block(at, command, body, start..index - 1, args, depth)?;
+        }
+        Ok(())
+    }
+
+    // Заголовок header уже с подставленными параметрами
+    fn block(
+        &mut self,
+        at: Location,
+        header: Command,
+        body: &Body,
+        range: Range<usize>,
+        args: Option<&[u64]>,
+        depth: usize,
+    ) -> Result<(), DisplayError> {
+        match header {
+            Command::Repeat { count } => {
+                if count > 0 && depth >= MAX_DEPTH {
+                    return Err(DisplayError::RecursionLimit {
+                        at,
+                        limit: MAX_DEPTH,
+                    });
+                }
+                for _ in 0..count {
+                    self.spend(at)?;
+                    self.body(body, range.clone(), args, depth + 1)?;
+                }
+            }
+            Command::Define { id, params } => {
+                let params = usize::try_from(params)
+                    .ok()
+                    .filter(|&params| params <= crate::command::MAX_MACRO_PARAMS)
+                    .ok_or(DisplayError::InvalidArgument {
+                        at,
+                        name: "parameter count",
+                        value: params,
+                    })?;
+                let body = body.clone();
+                self.machine.macros().insert(
+                    id,
+                    Macro {
+                        params,
+                        body,
+                        range,
+                    },
+                );
+            }
+            _ => unreachable!("only REPEAT and MACRO open blocks"),
+        }
+        Ok(())
+    }
+
+    // Команда command уже с подставленными параметрами
+    fn run(&mut self, at: Location, command: Command, depth: usize) -> Result<(), DisplayError> {
+        // Команды самого потока бюджет не тратят, только то, что порождают REPEAT и CALL
+        if depth > 0 {
+            self.spend(at)?;
+        }
+        match command {
+            Command::End => Err(DisplayError::UnexpectedEnd { at }),
+            // Маска без макроса или перед другой маской
+            Command::Params { mask } => Err(DisplayError::InvalidArgument {
+                at,
+                name: "parameters",
+                value: mask,
+            }),
+            Command::Call { id, args } => {
+                let Some(called) = self.machine.macros().get(&id).cloned() else {
+                    return Err(DisplayError::InvalidArgument {
+                        at,
+                        name: "macro",
+                        value: id,
+                    });
+                };
+                let args = args.as_slice();
+                if args.len() != called.params {
+                    return Err(DisplayError::InvalidArgument {
+                        at,
+                        name: "argument count",
+                        value: args.len() as u64,
+                    });
+                }
+                if depth >= MAX_DEPTH {
+                    return Err(DisplayError::RecursionLimit {
+                        at,
+                        limit: MAX_DEPTH,
+                    });
+                }
+                self.body(&called.body, called.range, Some(args), depth + 1)
+            }
+            command => self.machine.execute(at, command),
+        }
+    }
+
+    fn spend(&mut self, at: Location) -> Result<(), DisplayError> {
+        let budget = self.machine.instruction_budget();
+        if self.executed >= budget {
+            return Err(DisplayError::BudgetExceeded { at, budget });
+        }
+        self.executed += 1;
+        Ok(())
+    }
+}
+
+// Подставляет аргументы вызова в отмеченные mask аргументы команды. Отмеченный
+// аргумент - номер параметра, для MOVEBY аргумент вызова - i64 в дополнительном коде
+fn substitute(
+    command: Command,
+    at: Location,
+    args: Option<&[u64]>,
+    mask: u64,
+) -> Result<Command, DisplayError> {
+    if mask == 0 {
+        return Ok(command);
+    }
+    let mut words = Vec::with_capacity(command.encoded_len());
+    command.encode(&mut words);
+    let arguments = words.len() as u32 - 1;
+    let (Some(args), 0) = (args, mask.checked_shr(arguments).unwrap_or(0)) else {
+        return Err(DisplayError::InvalidArgument {
+            at,
+            name: "parameters",
+            value: mask,
+        });
+    };
+    for (slot, word) in words[1..].iter_mut().enumerate() {
+        if mask & (1 << slot) == 0 {
+            continue;
+        }
+        let value = usize::try_from(*word)
+            .ok()
+            .and_then(|index| args.get(index))
+            .ok_or(DisplayError::InvalidArgument {
+                at,
+                name: "parameter",
+                value: *word,
+            })?;
+        *word = match command {
+            Command::MoveBy { .. } => zigzag_encode(*value as i64),
+            _ => *value,
+        };
+    }
+    Command::decode(at, &mut words[1..].iter().copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DEFAULT_BUDGET, MAX_DEPTH};
+    use crate::cursor::zigzag_encode;
+    use crate::matrix::Matrix;
+    use crate::program::Program;
+    use crate::{create_display, process_commands, DisplayError, EdgePolicy};
+
+    #[test]
+    fn test_repeat() {
+        let mut display = create_display(4, 4, 1);
+        display.set_edge_policy(EdgePolicy::Wrap);
+        // Две строки по четыре пикселя вложенными повторами, пустой повтор пропускается
+        let stream = vec![21, 2, 21, 4, 9, 3, 22, 22, 21, 0, 2, 2, 22];
+        process_commands(&mut display, stream).unwrap();
+        let mut expected = Matrix::new(4, 4, 1);
+        for x in 0..4 {
+            for y in 0..2 {
+                expected.set_colour(x, y, 3);
+            }
+        }
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+        assert_eq!(display.current_pixel, (0, 2));
+    }
+
+    #[test]
+    fn test_macro_with_parameters() {
+        let mut display = create_display(4, 4, 1);
+        // MACRO 7 2 { MOVE $0 $0 PAINT $1 } CALL 7 (1, blue) CALL 7 (2, green)
+        let stream = vec![
+            23, 7, 2, 25, 3, 1, 0, 0, 25, 1, 2, 1, 22, 24, 7, 2, 1, 3, 24, 7, 2, 2, 2,
+        ];
+        process_commands(&mut display, stream).unwrap();
+        let mut expected = Matrix::new(4, 4, 1);
+        expected.set_colour(1, 1, 3);
+        expected.set_colour(2, 2, 2);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+
+        // Макрос остаётся в дисплее для следующих потоков
+        process_commands(&mut display, vec![24, 7, 2, 3, 3]).unwrap();
+        assert_eq!(display.matrix().colour(3, 3), 3);
+
+        let err = process_commands(&mut display, vec![24, 7, 1, 3]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #0 at offset 0: invalid argument count 1"
+        );
+        let err = process_commands(&mut display, vec![24, 8, 0]).unwrap_err();
+        assert_eq!(err.to_string(), "command #0 at offset 0: invalid macro 8");
+    }
+
+    #[test]
+    fn test_large_literals_in_macro() {
+        let mut display = create_display(4, 1, 1);
+        display.set_edge_policy(EdgePolicy::Clamp);
+        // Большие числа в теле макроса - обычные аргументы, а не ссылки на параметры
+        let far_left = zigzag_encode(-(1 << 62) - 1);
+        assert!(far_left >= 1 << 63);
+        let stream = vec![1, 3, 0, 23, 1, 0, 8, far_left, 0, 2, 3, 22, 24, 1, 0];
+        process_commands(&mut display, stream).unwrap();
+        assert_eq!(display.matrix().colour(0, 0), 3);
+
+        // Отрицательный аргумент для MOVEBY передаётся как есть, без zigzag
+        let stream = vec![23, 2, 1, 25, 1, 8, 0, 0, 2, 2, 22, 24, 2, 1, -2i64 as u64];
+        process_commands(&mut display, vec![1, 3, 0]).unwrap();
+        process_commands(&mut display, stream).unwrap();
+        assert_eq!(display.matrix().colour(1, 0), 2);
+
+        // REPEAT u64::MAX внутри макроса упирается в бюджет, а не в параметры
+        display.set_instruction_budget(10);
+        let err = process_commands(&mut display, vec![23, 3, 0, 21, u64::MAX, 22, 22, 24, 3, 0])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            DisplayError::BudgetExceeded { budget: 10, .. }
+        ));
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut display = create_display(2, 2, 1);
+        // Макрос, который вызывает сам себя
+        let err =
+            process_commands(&mut display, vec![23, 1, 0, 24, 1, 0, 22, 24, 1, 0]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #1 at offset 3: blocks and macro calls nested deeper than 64"
+        );
+
+        display.set_instruction_budget(100);
+        let err = process_commands(&mut display, vec![21, u64::MAX, 2, 1, 22]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #0 at offset 0: instruction budget of 100 commands exceeded"
+        );
+
+        let err = process_commands(&mut display, vec![21, 3, 2, 1]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #0 at offset 0: block is never closed"
+        );
+        let err = process_commands(&mut display, vec![22]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #0 at offset 0: end without repeat or macro"
+        );
+        // Ссылка на параметр, которого у макроса нет
+        let err =
+            process_commands(&mut display, vec![23, 2, 0, 25, 1, 2, 0, 22, 24, 2, 0]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #2 at offset 5: invalid parameter 0"
+        );
+        // Маска на аргумент, которого у команды нет, и маска вне макроса
+        let err =
+            process_commands(&mut display, vec![23, 3, 0, 25, 2, 2, 0, 22, 24, 3, 0]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #2 at offset 5: invalid parameters 2"
+        );
+        let err = process_commands(&mut display, vec![25, 1, 2, 0]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #0 at offset 0: invalid parameters 1"
+        );
+    }
+
+    #[test]
+    fn test_long_plain_stream() {
+        // Больше миллиона обычных команд: бюджет на них не тратится
+        let mut display = create_display(2, 2, 1);
+        let stream = std::iter::repeat_n([9, 3], DEFAULT_BUDGET as usize + 1).flatten();
+        display.set_edge_policy(EdgePolicy::Wrap);
+        process_commands(&mut display, stream).unwrap();
+        crate::assert_matrix_eq!(*display.matrix(), Matrix::new(2, 2, 3));
+    }
+
+    #[test]
+    fn test_nested_blocks() {
+        // REPEAT 1 { REPEAT 1 { ... PAINT 3 ... } }, вложенных блоков depth штук
+        fn nested(depth: usize) -> Vec<u64> {
+            let mut stream = [21, 1].repeat(depth);
+            stream.extend([2, 3]);
+            stream.extend(std::iter::repeat_n(22, depth));
+            stream
+        }
+        let mut display = create_display(2, 2, 1);
+        process_commands(&mut display, nested(MAX_DEPTH)).unwrap();
+        assert_eq!(display.matrix().colour(0, 0), 3);
+
+        let err = process_commands(&mut display, nested(10_000)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #64 at offset 128: blocks and macro calls nested deeper than 64"
+        );
+        let err = Program::compile(&nested(10_000)).unwrap_err();
+        assert!(matches!(
+            err.error(),
+            DisplayError::RecursionLimit {
+                limit: MAX_DEPTH,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,98 @@
+// Что происходит с курсором на краю дисплея
+
+/// Поведение курсора при переходе за край дисплея
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EdgePolicy {
+    /// Команда завершается ошибкой
+    #[default]
+    Error,
+    /// Курсор останавливается на ближайшем краю
+    Clamp,
+    /// Курсор переходит на следующую (или предыдущую) строку,
+    /// после последней строки - снова на первую
+    Wrap,
+}
+
+/// Где у дисплея строка y = 0. x всегда считается слева направо
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Origin {
+    /// Строка 0 сверху, y растёт вниз, как в Matrix
+    #[default]
+    TopLeft,
+    /// Строка 0 снизу, y растёт вверх
+    BottomLeft,
+}
+
+impl Origin {
+    /// Строка матрицы высотой height, в которой лежит строка y дисплея.
+    /// Преобразование симметрично: им же строка матрицы переводится обратно в y.
+    /// У пустого дисплея строк нет, для него возвращается 0
+    pub fn row(self, y: u64, height: u64) -> u64 {
+        match self {
+            Origin::TopLeft => y,
+            Origin::BottomLeft => height.saturating_sub(y + 1),
+        }
+    }
+}
+
+impl EdgePolicy {
+    /// Положение курсора после перехода в (x, y).
+    /// None, если политика Error и точка за краем, или если дисплей пустой
+    pub fn resolve(self, x: i128, y: i128, (width, height): (u32, u32)) -> Option<(u64, u64)> {
+        let (width, height) = (width as i128, height as i128);
+        if width == 0 || height == 0 {
+            return None;
+        }
+        match self {
+            EdgePolicy::Error => ((0..width).contains(&x) && (0..height).contains(&y))
+                .then_some((x as u64, y as u64)),
+            EdgePolicy::Clamp => {
+                Some((x.clamp(0, width - 1) as u64, y.clamp(0, height - 1) as u64))
+            }
+            EdgePolicy::Wrap => {
+                let index = (y * width + x).rem_euclid(width * height);
+                Some(((index % width) as u64, (index / width) as u64))
+            }
+        }
+    }
+}
+
+/// Знаковое число в беззнаковом потоке: 0, -1, 1, -2, 2... кодируются как 0, 1, 2, 3, 4...
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zigzag() {
+        for (value, encoded) in [(0, 0), (-1, 1), (1, 2), (-2, 3), (2, 4)] {
+            assert_eq!(zigzag_encode(value), encoded);
+            assert_eq!(zigzag_decode(encoded), value);
+        }
+        for value in [i64::MIN, i64::MAX] {
+            assert_eq!(zigzag_decode(zigzag_encode(value)), value);
+        }
+    }
+
+    #[test]
+    fn test_policies() {
+        let size = (4, 3);
+        assert_eq!(EdgePolicy::Error.resolve(3, 2, size), Some((3, 2)));
+        assert_eq!(EdgePolicy::Error.resolve(4, 0, size), None);
+        assert_eq!(EdgePolicy::Error.resolve(-1, 0, size), None);
+        assert_eq!(EdgePolicy::Clamp.resolve(9, -5, size), Some((3, 0)));
+        assert_eq!(EdgePolicy::Wrap.resolve(4, 0, size), Some((0, 1)));
+        assert_eq!(EdgePolicy::Wrap.resolve(-1, 1, size), Some((3, 0)));
+        assert_eq!(EdgePolicy::Wrap.resolve(4, 2, size), Some((0, 0)));
+        assert_eq!(EdgePolicy::Wrap.resolve(0, 0, (0, 3)), None);
+    }
+}
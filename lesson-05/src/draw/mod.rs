@@ -0,0 +1,147 @@
+// Растеризация фигур. Функции не знают про границы дисплея:
+// они передают каждую точку в plot, а тот решает, рисовать её или отбросить.
+
+/// Отрезок по алгоритму Брезенхэма, включая оба конца
+pub fn line(from: (i64, i64), to: (i64, i64), mut plot: impl FnMut(i64, i64)) {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let step_x = if x < to.0 { 1 } else { -1 };
+    let step_y = if y < to.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    loop {
+        plot(x, y);
+        if (x, y) == to {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Закрашенный прямоугольник с углами в from и to
+pub fn filled_rect(from: (i64, i64), to: (i64, i64), mut plot: impl FnMut(i64, i64)) {
+    for y in from.1.min(to.1)..=from.1.max(to.1) {
+        for x in from.0.min(to.0)..=from.0.max(to.0) {
+            plot(x, y);
+        }
+    }
+}
+
+/// Контур прямоугольника с углами в from и to, каждая точка ровно один раз
+pub fn outlined_rect(from: (i64, i64), to: (i64, i64), mut plot: impl FnMut(i64, i64)) {
+    let (left, right) = (from.0.min(to.0), from.0.max(to.0));
+    let (top, bottom) = (from.1.min(to.1), from.1.max(to.1));
+    for x in left..=right {
+        plot(x, top);
+        if bottom != top {
+            plot(x, bottom);
+        }
+    }
+    for y in top + 1..bottom {
+        plot(left, y);
+        if right != left {
+            plot(right, y);
+        }
+    }
+}
+
+/// Окружность по алгоритму средней точки
+pub fn circle(center: (i64, i64), radius: i64, mut plot: impl FnMut(i64, i64)) {
+    let (cx, cy) = center;
+    let mut x = radius;
+    let mut y = 0;
+    let mut decision = 1 - radius;
+    while x >= y {
+        // На осях и диагоналях октанты совпадают: нулевое смещение не отражается,
+        // а при x == y перестановка координат даёт те же точки
+        let swaps = if x == y { 1 } else { 2 };
+        for (a, b) in [(x, y), (y, x)].into_iter().take(swaps) {
+            for ox in mirrored(a) {
+                for oy in mirrored(b) {
+                    plot(cx + ox, cy + oy);
+                }
+            }
+        }
+        y += 1;
+        if decision < 0 {
+            decision += 2 * y + 1;
+        } else {
+            x -= 1;
+            decision += 2 * (y - x) + 1;
+        }
+    }
+}
+
+// value и -value, ноль один раз
+fn mirrored(value: i64) -> impl Iterator<Item = i64> {
+    [value, -value]
+        .into_iter()
+        .take(if value == 0 { 1 } else { 2 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(draw: impl FnOnce(&mut dyn FnMut(i64, i64))) -> Vec<(i64, i64)> {
+        let mut points = Vec::new();
+        draw(&mut |x, y| points.push((x, y)));
+        points
+    }
+
+    #[test]
+    fn test_line() {
+        let points = collect(|plot| line((0, 0), (4, 2), plot));
+        assert_eq!(points, vec![(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]);
+        let points = collect(|plot| line((2, 3), (2, 1), plot));
+        assert_eq!(points, vec![(2, 3), (2, 2), (2, 1)]);
+        let points = collect(|plot| line((1, 1), (1, 1), plot));
+        assert_eq!(points, vec![(1, 1)]);
+    }
+
+    #[test]
+    fn test_rects() {
+        assert_eq!(collect(|plot| filled_rect((2, 2), (0, 0), plot)).len(), 9);
+        let mut outline = collect(|plot| outlined_rect((0, 0), (2, 2), plot));
+        outline.sort_unstable();
+        assert_eq!(
+            outline,
+            vec![
+                (0, 0),
+                (0, 1),
+                (0, 2),
+                (1, 0),
+                (1, 2),
+                (2, 0),
+                (2, 1),
+                (2, 2)
+            ]
+        );
+        assert_eq!(collect(|plot| outlined_rect((0, 0), (0, 3), plot)).len(), 4);
+    }
+
+    #[test]
+    fn test_circle() {
+        let points = collect(|plot| circle((2, 2), 2, plot));
+        assert_eq!(points.len(), 12);
+        // Каждая точка рисуется один раз
+        for radius in 0..40 {
+            let mut points = collect(|plot| circle((0, 0), radius, plot));
+            let plotted = points.len();
+            points.sort_unstable();
+            points.dedup();
+            assert_eq!(points.len(), plotted, "radius {radius}");
+        }
+        assert!(points.contains(&(0, 2)) && points.contains(&(4, 2)));
+        assert!(points.contains(&(2, 0)) && points.contains(&(2, 4)));
+        assert_eq!(collect(|plot| circle((1, 1), 0, plot)), vec![(1, 1)]);
+    }
+}
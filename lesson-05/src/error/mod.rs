@@ -0,0 +1,157 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::command;
+
+/// Положение команды во входном потоке
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    /// Порядковый номер команды (с нуля)
+    pub command: usize,
+    /// Смещение опкода от начала потока
+    pub offset: usize,
+    pub opcode: u64,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "command #{} at offset {}", self.command, self.offset)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisplayError {
+    OutOfBounds {
+        at: Location,
+        x: u64,
+        y: u64,
+        boundaries: (u32, u32),
+    },
+    /// Относительный сдвиг курсора из from на by уводит за край
+    StepOutOfBounds {
+        at: Location,
+        from: (u64, u64),
+        by: (i64, i64),
+        boundaries: (u32, u32),
+    },
+    InvalidColour {
+        at: Location,
+        colour: u64,
+    },
+    UnknownOpcode {
+        at: Location,
+    },
+    /// Аргумент вне допустимого набора значений, name - его название
+    InvalidArgument {
+        at: Location,
+        name: &'static str,
+        value: u64,
+    },
+    MissingArguments {
+        at: Location,
+        expected: usize,
+        found: usize,
+    },
+    /// END без открытого REPEAT или MACRO
+    UnexpectedEnd {
+        at: Location,
+    },
+    /// Поток кончился, а блок, открытый командой at, так и не закрыт
+    UnclosedBlock {
+        at: Location,
+    },
+    /// Блоки REPEAT и вызовы макросов вложены глубже limit
+    RecursionLimit {
+        at: Location,
+        limit: usize,
+    },
+    /// FILLRECT или FILL закрасили бы больше limit пикселей, см. PixelStore::MAX_AREA
+    TooManyPixels {
+        at: Location,
+        limit: u64,
+    },
+    /// Повторы и макросы породили больше budget команд, см. Display::set_instruction_budget
+    BudgetExceeded {
+        at: Location,
+        budget: u64,
+    },
+}
+
+impl DisplayError {
+    pub fn location(&self) -> Location {
+        match *self {
+            DisplayError::OutOfBounds { at, .. }
+            | DisplayError::StepOutOfBounds { at, .. }
+            | DisplayError::InvalidColour { at, .. }
+            | DisplayError::UnknownOpcode { at }
+            | DisplayError::InvalidArgument { at, .. }
+            | DisplayError::MissingArguments { at, .. }
+            | DisplayError::UnexpectedEnd { at }
+            | DisplayError::UnclosedBlock { at }
+            | DisplayError::RecursionLimit { at, .. }
+            | DisplayError::TooManyPixels { at, .. }
+            | DisplayError::BudgetExceeded { at, .. } => at,
+        }
+    }
+}
+
+impl fmt::Display for DisplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayError::OutOfBounds {
+                at,
+                x,
+                y,
+                boundaries: (width, height),
+            } => {
+                let target = command::mnemonic(at.opcode).unwrap_or("point");
+                let target = target.to_ascii_lowercase();
+                write!(f, "{at}: {target} to ({x},{y}) outside {width}x{height}")
+            }
+            DisplayError::StepOutOfBounds {
+                at,
+                from: (x, y),
+                by: (dx, dy),
+                boundaries: (width, height),
+            } => {
+                let action = command::mnemonic(at.opcode).unwrap_or("step");
+                let action = action.to_ascii_lowercase();
+                write!(
+                    f,
+                    "{at}: {action} by ({dx},{dy}) from ({x},{y}) outside {width}x{height}"
+                )
+            }
+            DisplayError::InvalidColour { at, colour } => {
+                write!(f, "{at}: no such colour {colour}")
+            }
+            DisplayError::UnknownOpcode { at } => write!(f, "{at}: unknown opcode {}", at.opcode),
+            DisplayError::InvalidArgument { at, name, value } => {
+                write!(f, "{at}: invalid {name} {value}")
+            }
+            DisplayError::MissingArguments {
+                at,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{at}: opcode {} expects {expected} arguments, found {found}",
+                at.opcode
+            ),
+            DisplayError::UnexpectedEnd { at } => write!(f, "{at}: end without repeat or macro"),
+            DisplayError::UnclosedBlock { at } => write!(f, "{at}: block is never closed"),
+            DisplayError::RecursionLimit { at, limit } => {
+                write!(f, "{at}: blocks and macro calls nested deeper than {limit}")
+            }
+            DisplayError::TooManyPixels { at, limit } => {
+                let action = command::mnemonic(at.opcode).unwrap_or("command");
+                let action = action.to_ascii_lowercase();
+                write!(f, "{at}: {action} would paint more than {limit} pixels")
+            }
+            DisplayError::BudgetExceeded { at, budget } => {
+                write!(f, "{at}: instruction budget of {budget} commands exceeded")
+            }
+        }
+    }
+}
+
+impl Error for DisplayError {}
@@ -0,0 +1,143 @@
+// История применённых команд. Каждая запись хранит только изменённые пиксели
+// и положение курсора до и после команды, а не копию всей матрицы. Одна
+// заливка большого холста меняет миллионы пикселей, поэтому история ограничена
+// не только числом записей, но и общим числом изменённых пикселей.
+
+use std::collections::VecDeque;
+
+pub const DEFAULT_LIMIT: usize = 1024;
+/// Сколько изменённых пикселей всех записей хранится по умолчанию (около 128 МБ)
+pub const DEFAULT_CHANGE_LIMIT: usize = 1 << 22;
+
+/// Отметка в истории, к которой можно откатиться через Display::undo_to_checkpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint(u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Change {
+    pub layer: usize,
+    pub x: u64,
+    pub y: u64,
+    pub before: u8,
+    pub after: u8,
+}
+
+#[derive(Debug)]
+pub(crate) struct Entry {
+    id: u64,
+    pub cursor_before: (u64, u64),
+    pub cursor_after: (u64, u64),
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug)]
+pub(crate) struct History {
+    done: VecDeque<Entry>,
+    undone: Vec<Entry>,
+    limit: usize,
+    change_limit: usize,
+    // Сколько изменённых пикселей лежит в done и undone вместе
+    changes: usize,
+    next_id: u64,
+    // Состояние, с которого начинается сохранённая история
+    base: u64,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            done: VecDeque::new(),
+            undone: Vec::new(),
+            limit,
+            change_limit: DEFAULT_CHANGE_LIMIT,
+            changes: 0,
+            next_id: 1,
+            base: 0,
+        }
+    }
+
+    /// Забывает всю историю, старые отметки становятся недостижимыми
+    pub fn clear(&mut self) {
+        self.undone.clear();
+        self.done.clear();
+        self.changes = 0;
+        self.base = self.next_id;
+        self.next_id += 1;
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    pub fn change_limit(&self) -> usize {
+        self.change_limit
+    }
+
+    pub fn set_change_limit(&mut self, change_limit: usize) {
+        self.change_limit = change_limit;
+        self.trim();
+    }
+
+    /// Запоминает новую команду. Отменённые команды после этого повторить нельзя.
+    /// Запись больше лимита пикселей не хранится, и отменить её нельзя
+    pub fn record(
+        &mut self,
+        cursor_before: (u64, u64),
+        cursor_after: (u64, u64),
+        changes: Vec<Change>,
+    ) {
+        let undone: usize = self.undone.drain(..).map(|entry| entry.changes.len()).sum();
+        self.changes -= undone;
+        self.changes += changes.len();
+        self.done.push_back(Entry {
+            id: self.next_id,
+            cursor_before,
+            cursor_after,
+            changes,
+        });
+        self.next_id += 1;
+        self.trim();
+    }
+
+    /// Переносит последнюю команду в список отменённых и возвращает её для отката
+    pub fn undo(&mut self) -> Option<&Entry> {
+        let entry = self.done.pop_back()?;
+        self.undone.push(entry);
+        self.undone.last()
+    }
+
+    /// Возвращает последнюю отменённую команду для повторного применения
+    pub fn redo(&mut self) -> Option<&Entry> {
+        let entry = self.undone.pop()?;
+        self.done.push_back(entry);
+        self.done.back()
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.done.back().map_or(self.base, |entry| entry.id))
+    }
+
+    /// Сколько команд нужно отменить, чтобы вернуться к отметке.
+    /// None, если отметка уже вытеснена из истории или лежит среди отменённых
+    pub fn undos_to(&self, checkpoint: Checkpoint) -> Option<usize> {
+        if checkpoint.0 == self.base {
+            return Some(self.done.len());
+        }
+        self.done
+            .iter()
+            .rev()
+            .position(|entry| entry.id == checkpoint.0)
+    }
+
+    // Вытесняет старые записи. Отменённые не трогает: их вытеснит следующая record
+    fn trim(&mut self) {
+        while self.done.len() > self.limit || self.changes > self.change_limit {
+            let Some(entry) = self.done.pop_front() else {
+                break;
+            };
+            self.changes -= entry.changes.len();
+            self.base = entry.id;
+        }
+    }
+}
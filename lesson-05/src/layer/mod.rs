@@ -0,0 +1,55 @@
+// Слой дисплея: своя матрица, видимость и прозрачный цвет.
+// Слои лежат в Display снизу вверх, порядок в списке и есть z-порядок.
+// Матрица слоя - любое хранилище пикселей, по умолчанию обычная Matrix.
+
+use crate::matrix::Matrix;
+use crate::store::PixelStore;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Layer<S = Matrix> {
+    pub(crate) matrix: S,
+    pub(crate) visible: bool,
+    pub(crate) transparent: Option<u8>,
+}
+
+impl<S: PixelStore> Layer<S> {
+    /// Непрозрачный слой, например нижний слой дисплея
+    pub fn opaque(matrix: S) -> Self {
+        Self {
+            matrix,
+            visible: true,
+            transparent: None,
+        }
+    }
+
+    /// Слой, пиксели цвета transparent которого пропускают нижние слои
+    pub fn transparent(width: u32, height: u32, transparent: u8) -> Self {
+        Self {
+            matrix: S::new(width, height, transparent),
+            visible: true,
+            transparent: Some(transparent),
+        }
+    }
+
+    pub fn matrix(&self) -> &S {
+        &self.matrix
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn transparent_colour(&self) -> Option<u8> {
+        self.transparent
+    }
+}
+
+/// Накладывает видимые слои снизу вверх на фон цвета background
+pub fn flatten<S: PixelStore>(layers: &[Layer<S>], width: u32, height: u32, background: u8) -> S {
+    let mut result = S::new(width, height, background);
+    for layer in layers.iter().filter(|layer| layer.visible) {
+        result.overlay(&layer.matrix, layer.transparent);
+    }
+    result
+}
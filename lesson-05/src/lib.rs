@@ -0,0 +1,1272 @@
+// Вам нужно реализовать программу обработки команд для дисплея.
+
+// На вход пользователь подает:
+// * 2 числа: размер дисплея
+// * 1 число: цвет дисплея по-умолчанию (1 - красный, 2 - зеленый, 3 - синий,
+//   другие номера можно задать своей палитрой)
+// * Последовательность команд: набор чисел.
+//
+// Дисплей поддерживает следующие команды:
+// * 1 x y - переместить курсор в позицию x y
+// * 2 colour - перекрасить пиксель в цвет colour
+// * 3 x y colour - нарисовать отрезок от курсора до x y, курсор переходит в x y
+// * 4 x y colour - нарисовать контур прямоугольника между курсором и x y
+// * 5 x y colour - закрасить прямоугольник между курсором и x y
+// * 6 radius colour - нарисовать окружность с центром в курсоре (обрезается по краям)
+// * 7 connectivity colour - залить область под курсором, соседи 4- или 8-связные
+// * 8 dx dy - сдвинуть курсор на dx dy (знаковые числа в кодировке zigzag: 0, -1, 1, -2... -> 0, 1, 2, 3...)
+// * 9 colour - перекрасить пиксель и сдвинуть курсор на шаг вправо
+// * 10 layer - сделать слой layer активным (0 - нижний слой)
+// * 11 w h - запомнить область w x h от курсора как спрайт (номера спрайтов идут с 0)
+// * 12 sprite - нарисовать спрайт от курсора
+// * 13 sprite transparent - нарисовать спрайт, не трогая пиксели цвета transparent
+// * 14, 15, 16 - повернуть дисплей на 90, 180 или 270 градусов по часовой стрелке
+// * 17, 18 - отразить дисплей слева направо или сверху вниз
+// * 19 - транспонировать дисплей
+// * 20 w h - масштабировать дисплей до w x h (ближайший сосед)
+// * 21 count ... 22 - повторить команды до парной 22 (END) count раз
+// * 23 id params ... 22 - запомнить команды до END как макрос id с params параметрами
+// * 24 id argc args... - выполнить макрос id с argc аргументами
+// * 25 mask - внутри макроса: аргументы следующей команды, отмеченные битами mask,
+//   - номера параметров (MOVEBY получает параметр как i64 в дополнительном коде)
+//
+// Координаты: x - столбец слева направо (0..ширина), y - строка (0..высота).
+// По умолчанию строка 0 верхняя (Origin::TopLeft), Display::set_origin(Origin::BottomLeft)
+// переносит начало в левый нижний угол, и y растёт вверх. Матрица всегда хранит
+// строки сверху вниз, пиксель (x, y) матрицы лежит в строке y.
+//
+// За край дисплея курсор по умолчанию не пускается (ошибка), но Display::set_edge_policy
+// позволяет останавливать его на краю или переносить на следующую строку.
+//
+// Поток из ненадёжного источника можно проверить целиком до выполнения:
+// program::Program::compile и Program::run отвергают его, не трогая дисплей.
+//
+// Огромные холсты, где почти всё закрашено фоном, не обязаны хранить каждый пиксель:
+// create_display_with_store::<store::SparseMatrix>(...) или store::TiledMatrix.
+//
+// Пример входных данных:
+// 4 4
+// 1
+// 1 2 2 2 3
+// В результате пиксель по позиции (2,2) будет перекрашен в синий цвет
+
+// Обновлять состояние дисплея нужно через метод matrix.set_colour(pos_x, pos_y, colour)
+
+// Важно! Обязательна проверка на ошибки. Если пользователь просит переместиться на пиксель
+// за пределами дисплея, ввел неправильный цвет или оборвал команду, то process_commands
+// возвращает DisplayError с номером команды, её смещением в потоке и ошибочными значениями.
+
+pub mod cli;
+pub mod command;
+pub mod compile;
+mod control;
+pub mod cursor;
+mod draw;
+mod error;
+mod history;
+pub mod layer;
+pub mod matrix;
+pub mod palette;
+pub mod program;
+pub mod render;
+pub mod repl;
+pub mod script;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod store;
+pub mod stream;
+
+pub use command::Command;
+use control::{Interpreter, Macro};
+pub use cursor::{EdgePolicy, Origin};
+pub use error::{DisplayError, Location};
+pub use history::Checkpoint;
+use history::{Change, History};
+use layer::Layer;
+use matrix::{Connectivity, Matrix, SvgOptions, Transform};
+use palette::Palette;
+use program::Op;
+use std::collections::HashMap;
+use store::PixelStore;
+
+/// Дисплей со слоями в хранилище S. Для огромных холстов вместо Matrix
+/// подойдут store::SparseMatrix или store::TiledMatrix
+pub struct Display<S = Matrix> {
+    // можете добавить сюда любые дополнительные поля
+    current_pixel: (u64, u64),
+    boundaries: (u32, u32),
+    // Слои снизу вверх, нижний всегда непрозрачный
+    layers: Vec<Layer<S>>,
+    active_layer: usize,
+    background: u8,
+    palette: Palette,
+    edge_policy: EdgePolicy,
+    origin: Origin,
+    history: History,
+    // Изменения пикселей текущей команды, попадут в историю после её выполнения
+    pending: Vec<Change>,
+    sprites: Vec<Matrix>,
+    // Макросы, определённые командой 23, по номеру
+    macros: HashMap<u64, Macro>,
+    // Сколько команд можно выполнить за один вызов process_commands
+    instruction_budget: u64,
+}
+
+impl<S: PixelStore> Display<S> {
+    /// Матрица активного слоя. Итоговое изображение всех слоёв - flatten()
+    pub fn matrix(&self) -> &S {
+        &self.layers[self.active_layer].matrix
+    }
+
+    /// Видимые слои, наложенные друг на друга с учётом прозрачности
+    pub fn flatten(&self) -> S {
+        let (width, height) = self.boundaries;
+        layer::flatten(&self.layers, width, height, self.background)
+    }
+
+    pub fn layers(&self) -> &[Layer<S>] {
+        &self.layers
+    }
+
+    pub fn active_layer(&self) -> usize {
+        self.active_layer
+    }
+
+    /// Делает слой активным, команды рисования пойдут в него
+    pub fn select_layer(&mut self, index: usize) -> bool {
+        if index >= self.layers.len() {
+            return false;
+        }
+        self.active_layer = index;
+        true
+    }
+
+    /// Добавляет прозрачный слой поверх остальных и возвращает его номер.
+    /// Изменение набора слоёв очищает историю отмены
+    pub fn add_layer(&mut self, transparent: u8) -> usize {
+        let (width, height) = self.boundaries;
+        self.layers
+            .push(Layer::transparent(width, height, transparent));
+        self.history.clear();
+        self.layers.len() - 1
+    }
+
+    /// Удаляет слой. Нижний слой удалить нельзя
+    pub fn remove_layer(&mut self, index: usize) -> bool {
+        if index == 0 || index >= self.layers.len() {
+            return false;
+        }
+        self.layers.remove(index);
+        if self.active_layer >= index {
+            self.active_layer -= 1;
+        }
+        self.history.clear();
+        true
+    }
+
+    /// Переносит слой на другое место в z-порядке. Нижний слой остаётся на месте
+    pub fn move_layer(&mut self, from: usize, to: usize) -> bool {
+        let count = self.layers.len();
+        if from == 0 || to == 0 || from >= count || to >= count {
+            return false;
+        }
+        let active = self.active_layer;
+        let layer = self.layers.remove(from);
+        self.layers.insert(to, layer);
+        self.active_layer = match active {
+            a if a == from => to,
+            a if from < a && a <= to => a - 1,
+            a if to <= a && a < from => a + 1,
+            a => a,
+        };
+        self.history.clear();
+        true
+    }
+
+    /// Поворачивает или отражает все слои. Курсор переезжает вместе со своим
+    /// пикселем, история отмены очищается
+    pub fn transform(&mut self, transform: Transform) {
+        (self.current_pixel, self.boundaries) =
+            transform_cursor(self.current_pixel, self.boundaries, self.origin, transform);
+        for layer in &mut self.layers {
+            layer.matrix = layer.matrix.transform(transform);
+        }
+        self.history.clear();
+    }
+
+    /// Масштабирует все слои до width x height, курсор сохраняет относительное положение.
+    /// Пустой дисплей растягивать не из чего, его слои заполняются фоном или
+    /// прозрачным цветом. История отмены очищается
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let empty = self.boundaries.0 == 0 || self.boundaries.1 == 0;
+        self.current_pixel = scale_cursor(
+            self.current_pixel,
+            self.boundaries,
+            (width, height),
+            self.origin,
+        );
+        self.boundaries = (width, height);
+        for layer in &mut self.layers {
+            layer.matrix = if empty {
+                let colour = layer.transparent.unwrap_or(self.background);
+                S::new(width, height, colour)
+            } else {
+                layer.matrix.resize(width as usize, height as usize)
+            };
+        }
+        self.history.clear();
+    }
+
+    /// Добавляет спрайт в библиотеку и возвращает его номер для команд 12 и 13
+    pub fn add_sprite(&mut self, sprite: Matrix) -> usize {
+        self.sprites.push(sprite);
+        self.sprites.len() - 1
+    }
+
+    pub fn sprites(&self) -> &[Matrix] {
+        &self.sprites
+    }
+
+    pub fn set_layer_visible(&mut self, index: usize, visible: bool) -> bool {
+        match self.layers.get_mut(index) {
+            Some(layer) => {
+                layer.visible = visible;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn edge_policy(&self) -> EdgePolicy {
+        self.edge_policy
+    }
+
+    /// Что делать, когда перемещение уводит курсор за край дисплея
+    pub fn set_edge_policy(&mut self, policy: EdgePolicy) {
+        self.edge_policy = policy;
+    }
+
+    pub fn origin(&self) -> Origin {
+        self.origin
+    }
+
+    /// Переносит начало координат. Курсор остаётся на том же пикселе,
+    /// история отмены очищается
+    pub fn set_origin(&mut self, origin: Origin) {
+        let (x, y) = self.current_pixel;
+        let height = self.boundaries.1 as u64;
+        self.current_pixel = (x, origin.row(self.origin.row(y, height), height));
+        self.origin = origin;
+        self.history.clear();
+    }
+
+    /// Сколько команд из повторов и тел макросов можно выполнить за один вызов
+    /// process_commands. Обычные команды потока не считаются
+    pub fn set_instruction_budget(&mut self, budget: u64) {
+        self.instruction_budget = budget;
+    }
+
+    /// Сколько последних команд хранится для отмены, 0 отключает историю
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.set_limit(limit);
+    }
+
+    /// Сколько изменённых пикселей всех команд хранится для отмены. Старые команды
+    /// вытесняются, команда, изменившая больше пикселей, не сохраняется вовсе
+    pub fn set_history_change_limit(&mut self, changes: usize) {
+        self.history.set_change_limit(changes);
+    }
+
+    /// Отменяет последнюю команду, false если отменять нечего
+    pub fn undo(&mut self) -> bool {
+        let Some(entry) = self.history.undo() else {
+            return false;
+        };
+        for change in entry.changes.iter().rev() {
+            self.layers[change.layer]
+                .matrix
+                .set_colour(change.x, change.y, change.before);
+        }
+        self.current_pixel = entry.cursor_before;
+        true
+    }
+
+    /// Повторяет последнюю отменённую команду, false если повторять нечего
+    pub fn redo(&mut self) -> bool {
+        let Some(entry) = self.history.redo() else {
+            return false;
+        };
+        for change in &entry.changes {
+            self.layers[change.layer]
+                .matrix
+                .set_colour(change.x, change.y, change.after);
+        }
+        self.current_pixel = entry.cursor_after;
+        true
+    }
+
+    /// Отметка текущего состояния для undo_to_checkpoint
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.history.checkpoint()
+    }
+
+    /// Отменяет все команды после отметки. Если отметка уже вытеснена
+    /// из истории или была отменена, дисплей не меняется и возвращается false
+    pub fn undo_to_checkpoint(&mut self, checkpoint: Checkpoint) -> bool {
+        let Some(undos) = self.history.undos_to(checkpoint) else {
+            return false;
+        };
+        for _ in 0..undos {
+            self.undo();
+        }
+        true
+    }
+
+    fn execute(&mut self, command: &Command, at: Location) -> Result<(), DisplayError> {
+        let op = program::check(self, command, at)?;
+        self.perform(op);
+        Ok(())
+    }
+
+    // Выполняет уже проверенную команду и записывает её в историю
+    pub(crate) fn perform(&mut self, op: Op) {
+        let cursor = self.current_pixel;
+        self.apply(op);
+        let changes = std::mem::take(&mut self.pending);
+        // После смены размеров старые координаты в истории не имеют смысла
+        let reshaped = matches!(op, Op::Transform { .. } | Op::Resize { .. });
+        if !reshaped && (!changes.is_empty() || cursor != self.current_pixel) {
+            self.history.record(cursor, self.current_pixel, changes);
+        }
+    }
+
+    fn apply(&mut self, op: Op) {
+        match op {
+            Op::MoveTo { to } => self.current_pixel = to,
+            Op::SelectLayer { layer } => self.active_layer = layer,
+            Op::Copy { width, height } => {
+                // Спрайт хранится так, как выглядит на экране: строки сверху вниз
+                let (x, y) = self.current_pixel;
+                let sprite = match self.origin {
+                    Origin::TopLeft => self.matrix().copy_region(x, y, width, height),
+                    Origin::BottomLeft => {
+                        let top = y.saturating_add(height).min(self.boundaries.1 as u64);
+                        let row = self.boundaries.1 as u64 - top;
+                        self.matrix().copy_region(x, row, width, top - y)
+                    }
+                };
+                self.sprites.push(sprite);
+            }
+            Op::Stamp {
+                sprite,
+                transparent,
+            } => {
+                // Угол спрайта в курсоре, остальное в сторону роста x и y
+                let sprite = self.sprites[sprite].clone();
+                let height = sprite.height() as u64;
+                let (x, y) = self.cursor();
+                for (dx, row, colour) in sprite.pixels() {
+                    if Some(colour) != transparent {
+                        let dy = self.origin.row(row, height);
+                        self.paint(x + dx as i64, y + dy as i64, colour);
+                    }
+                }
+            }
+            Op::Transform { transform } => self.transform(transform),
+            Op::Resize { width, height } => self.resize(width, height),
+            Op::PaintNext { colour, next } => {
+                let (x, y) = self.current_pixel;
+                self.paint(x as i64, y as i64, colour);
+                self.current_pixel = next;
+            }
+            Op::Paint { colour } => {
+                let (x, y) = self.current_pixel;
+                self.paint(x as i64, y as i64, colour);
+            }
+            Op::LineTo { to, colour } => {
+                draw::line(self.cursor(), (to.0 as i64, to.1 as i64), |x, y| {
+                    self.paint(x, y, colour)
+                });
+                self.current_pixel = to;
+            }
+            Op::Rect { to, colour } => {
+                draw::outlined_rect(self.cursor(), (to.0 as i64, to.1 as i64), |x, y| {
+                    self.paint(x, y, colour)
+                });
+            }
+            Op::FillRect { to, colour } => {
+                draw::filled_rect(self.cursor(), (to.0 as i64, to.1 as i64), |x, y| {
+                    self.paint(x, y, colour)
+                });
+            }
+            Op::Circle { radius, colour } => {
+                // Окружность с радиусом больше суммы сторон не задевает дисплей
+                let (width, height) = self.boundaries;
+                if radius > width as u64 + height as u64 {
+                    return;
+                }
+                let radius = radius as i64;
+                draw::circle(self.cursor(), radius, |x, y| self.paint(x, y, colour));
+            }
+            Op::Fill {
+                connectivity,
+                colour,
+            } => {
+                let (x, row) = self.pixel(self.current_pixel);
+                let region = self.matrix().region(x, row, connectivity, S::MAX_AREA);
+                for (x, row) in region.expect("fill area is checked") {
+                    self.paint_pixel(x, row, colour);
+                }
+            }
+        }
+    }
+
+    fn cursor(&self) -> (i64, i64) {
+        (self.current_pixel.0 as i64, self.current_pixel.1 as i64)
+    }
+
+    // Можно ли рисовать цветом colour на слое layer
+    fn check_colour_in(&self, layer: usize, colour: u8) -> bool {
+        // Прозрачным цветом слоя можно стирать, даже если его нет в палитре
+        self.palette.contains(colour) || Some(colour) == self.layers[layer].transparent
+    }
+
+    // Пиксель матрицы (столбец, строка), в котором лежит точка дисплея (x, y)
+    fn pixel(&self, (x, y): (u64, u64)) -> (u64, u64) {
+        (x, self.origin.row(y, self.boundaries.1 as u64))
+    }
+
+    // Рисование в координатах дисплея, точки за границами отбрасываются
+    fn paint(&mut self, x: i64, y: i64, colour: u8) {
+        let (width, height) = self.boundaries;
+        if !(0..width as i64).contains(&x) || !(0..height as i64).contains(&y) {
+            return;
+        }
+        let (x, row) = self.pixel((x as u64, y as u64));
+        self.paint_pixel(x, row, colour);
+    }
+
+    // Все изменения пикселей проходят здесь. История хранит координаты матрицы
+    fn paint_pixel(&mut self, x: u64, row: u64, colour: u8) {
+        let layer = self.active_layer;
+        let matrix = &mut self.layers[layer].matrix;
+        let before = matrix.colour(x, row);
+        if before != colour {
+            matrix.set_colour(x, row, colour);
+            // Команду больше лимита история всё равно не сохранит, копить её незачем:
+            // одного лишнего изменения хватает, чтобы record её отбросила
+            if self.pending.len() > self.history.change_limit() {
+                return;
+            }
+            self.pending.push(Change {
+                layer,
+                x,
+                y: row,
+                before,
+                after: colour,
+            });
+        }
+    }
+}
+
+impl Display {
+    /// Снимок дисплея в SVG с подсвеченным курсором
+    pub fn to_svg(&self, cell_size: u32, grid: bool) -> String {
+        let options = SvgOptions {
+            grid,
+            cursor: Some(self.pixel(self.current_pixel)),
+        };
+        self.flatten()
+            .to_svg_with_options(cell_size, &self.palette, &options)
+    }
+}
+
+impl<S: PixelStore> program::State for Display<S> {
+    fn position(&self) -> (u64, u64) {
+        self.current_pixel
+    }
+
+    fn boundaries(&self) -> (u32, u32) {
+        self.boundaries
+    }
+
+    fn edge_policy(&self) -> EdgePolicy {
+        self.edge_policy
+    }
+
+    fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    fn max_area(&self) -> u64 {
+        S::MAX_AREA
+    }
+
+    fn fill_fits(&self, connectivity: Connectivity) -> bool {
+        let (width, height) = self.boundaries;
+        if width as u64 * height as u64 <= S::MAX_AREA {
+            return true;
+        }
+        let (x, row) = self.pixel(self.current_pixel);
+        self.matrix()
+            .region(x, row, connectivity, S::MAX_AREA)
+            .is_some()
+    }
+
+    fn check_colour(&self, colour: u64, at: Location) -> Result<u8, DisplayError> {
+        match u8::try_from(colour) {
+            Ok(index) if self.check_colour_in(self.active_layer, index) => Ok(index),
+            _ => Err(DisplayError::InvalidColour { at, colour }),
+        }
+    }
+
+    // Спрайт рисуется целиком или никак: цвета проверяются до рисования
+    fn check_sprite(
+        &self,
+        sprite: u64,
+        transparent: Option<u8>,
+        at: Location,
+    ) -> Result<usize, DisplayError> {
+        let index = usize::try_from(sprite)
+            .ok()
+            .filter(|&index| index < self.sprites.len())
+            .ok_or(DisplayError::InvalidArgument {
+                at,
+                name: "sprite",
+                value: sprite,
+            })?;
+        for (_, _, colour) in self.sprites[index].pixels() {
+            if Some(colour) != transparent {
+                self.check_colour(colour as u64, at)?;
+            }
+        }
+        Ok(index)
+    }
+}
+
+// Курсор и размеры дисплея после поворота или отражения. Курсор остаётся
+// на своём пикселе, поэтому пересчёт идёт через строки матрицы
+pub(crate) fn transform_cursor(
+    (x, y): (u64, u64),
+    (width, height): (u32, u32),
+    origin: Origin,
+    transform: Transform,
+) -> ((u64, u64), (u32, u32)) {
+    let (width, height) = (width as usize, height as usize);
+    if width == 0 || height == 0 {
+        let (width, height) = transform.size(width, height);
+        return ((0, 0), (width as u32, height as u32));
+    }
+    let row = origin.row(y, height as u64) as usize;
+    let (x, row) = transform.map(x as usize, row, width, height);
+    let (width, height) = transform.size(width, height);
+    let y = origin.row(row as u64, height as u64);
+    ((x as u64, y), (width as u32, height as u32))
+}
+
+// Куда попадает курсор при масштабировании дисплея from до to. С пустого
+// дисплея и на пустой дисплей курсор переходит в начало координат
+pub(crate) fn scale_cursor(
+    (x, y): (u64, u64),
+    from: (u32, u32),
+    to: (u32, u32),
+    origin: Origin,
+) -> (u64, u64) {
+    if from.0 == 0 || from.1 == 0 || to.0 == 0 || to.1 == 0 {
+        return (0, 0);
+    }
+    let row = origin.row(y, from.1 as u64) * to.1 as u64 / from.1 as u64;
+    (
+        x * to.0 as u64 / from.0 as u64,
+        origin.row(row, to.1 as u64),
+    )
+}
+
+pub fn create_display(max_width: u32, max_height: u32, default_colour: u8) -> Display {
+    // ваш код сюда
+    create_display_with_palette(max_width, max_height, default_colour, Palette::default())
+}
+
+/// Дисплей, цвета которого проверяются по заданной палитре
+pub fn create_display_with_palette(
+    max_width: u32,
+    max_height: u32,
+    default_colour: u8,
+    palette: Palette,
+) -> Display {
+    create_display_with_store(max_width, max_height, default_colour, palette)
+}
+
+/// Дисплей, слои которого хранятся в S, например
+/// create_display_with_store::<SparseMatrix>(1_000_000, 1_000_000, 1, palette)
+pub fn create_display_with_store<S: PixelStore>(
+    max_width: u32,
+    max_height: u32,
+    default_colour: u8,
+    palette: Palette,
+) -> Display<S> {
+    Display {
+        current_pixel: (0, 0),
+        boundaries: (max_width, max_height),
+        layers: vec![Layer::opaque(S::new(max_width, max_height, default_colour))],
+        active_layer: 0,
+        background: default_colour,
+        palette,
+        edge_policy: EdgePolicy::default(),
+        origin: Origin::default(),
+        history: History::new(history::DEFAULT_LIMIT),
+        pending: Vec::new(),
+        sprites: Vec::new(),
+        macros: HashMap::new(),
+        instruction_budget: control::DEFAULT_BUDGET,
+    }
+}
+
+/// Дисплей с уже готовым содержимым, например загруженным из файла
+pub fn create_display_from_matrix(matrix: Matrix, palette: Palette) -> Display {
+    // Фон виден только если нижний слой скрыть
+    let background = matrix
+        .rows()
+        .next()
+        .and_then(|row| row.first())
+        .copied()
+        .unwrap_or(0);
+    Display {
+        current_pixel: (0, 0),
+        boundaries: (matrix.width() as u32, matrix.height() as u32),
+        layers: vec![Layer::opaque(matrix)],
+        active_layer: 0,
+        background,
+        palette,
+        edge_policy: EdgePolicy::default(),
+        origin: Origin::default(),
+        history: History::new(history::DEFAULT_LIMIT),
+        pending: Vec::new(),
+        sprites: Vec::new(),
+        macros: HashMap::new(),
+        instruction_budget: control::DEFAULT_BUDGET,
+    }
+}
+
+/// Применяет команды по мере их поступления. Подходит и для Vec, и для
+/// бесконечных итераторов: поток не собирается в память целиком
+pub fn process_commands<S: PixelStore>(
+    display: &mut Display<S>,
+    input: impl IntoIterator<Item = u64>,
+) -> Result<(), DisplayError> {
+    let mut interpreter = Interpreter::new(display);
+    for decoded in command::decode(input) {
+        let (at, command) = decoded?;
+        interpreter.feed(at, command)?;
+    }
+    interpreter.finish()
+}
+
+/// Применяет уже разобранные команды (например, из текстового скрипта).
+/// Смещения в ошибках считаются так, как если бы команды были закодированы в поток
+pub fn execute_commands<S: PixelStore>(
+    display: &mut Display<S>,
+    commands: &[Command],
+) -> Result<(), DisplayError> {
+    let mut interpreter = Interpreter::new(display);
+    let mut offset = 0;
+    for (index, command) in commands.iter().enumerate() {
+        let at = Location {
+            command: index,
+            offset,
+            opcode: command.opcode(),
+        };
+        interpreter.feed(at, *command)?;
+        offset += command.encoded_len();
+    }
+    interpreter.finish()
+}
+
+// тесты
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_happy_case() {
+        let mut display = create_display(4, 4, 1);
+        process_commands(&mut display, vec![1, 2, 2, 2, 3]).unwrap();
+        let mut expected = Matrix::new(4, 4, 1);
+        expected.set_colour(2, 2, 3);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+    }
+
+    #[test]
+    fn test_error() {
+        let mut display = create_display(4, 4, 1);
+        let err = process_commands(&mut display, vec![1, 5, 5, 2, 3]).unwrap_err();
+        assert_eq!(
+            err,
+            DisplayError::OutOfBounds {
+                at: Location {
+                    command: 0,
+                    offset: 0,
+                    opcode: 1
+                },
+                x: 5,
+                y: 5,
+                boundaries: (4, 4),
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_on_boundary() {
+        let mut display = create_display(4, 4, 1);
+        let err = process_commands(&mut display, vec![1, 4, 0, 2, 3]).unwrap_err();
+        assert!(matches!(err, DisplayError::OutOfBounds { x: 4, y: 0, .. }));
+    }
+
+    #[test]
+    fn test_error_invalid_colour() {
+        let mut display = create_display(4, 4, 1);
+        let err = process_commands(&mut display, vec![1, 2, 2, 2, 5]).unwrap_err();
+        assert_eq!(
+            err,
+            DisplayError::InvalidColour {
+                at: Location {
+                    command: 1,
+                    offset: 3,
+                    opcode: 2
+                },
+                colour: 5,
+            }
+        );
+        // 257 не должен превратиться в 1 при приведении к u8
+        let err = process_commands(&mut display, vec![2, 257]).unwrap_err();
+        assert!(matches!(
+            err,
+            DisplayError::InvalidColour { colour: 257, .. }
+        ));
+    }
+
+    #[test]
+    fn test_error_invalid_command() {
+        let mut display = create_display(4, 4, 1);
+        let err = process_commands(&mut display, vec![1, 2, 2, 42, 5]).unwrap_err();
+        assert_eq!(
+            err,
+            DisplayError::UnknownOpcode {
+                at: Location {
+                    command: 1,
+                    offset: 3,
+                    opcode: 42
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_truncated() {
+        let mut display = create_display(4, 4, 1);
+        let err = process_commands(&mut display, vec![1, 2, 2, 1, 3]).unwrap_err();
+        assert_eq!(
+            err,
+            DisplayError::MissingArguments {
+                at: Location {
+                    command: 1,
+                    offset: 3,
+                    opcode: 1
+                },
+                expected: 2,
+                found: 1,
+            }
+        );
+        assert!(process_commands(&mut display, vec![2]).is_err());
+    }
+
+    #[test]
+    fn test_error_message() {
+        let mut display = create_display(4, 4, 1);
+        let err = process_commands(&mut display, vec![2, 1, 2, 1, 2, 1, 1, 5, 5]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #3 at offset 6: move to (5,5) outside 4x4"
+        );
+    }
+
+    #[test]
+    fn test_line() {
+        let mut display = create_display(4, 4, 1);
+        process_commands(&mut display, vec![1, 0, 0, 3, 3, 3, 2]).unwrap();
+        let mut expected = Matrix::new(4, 4, 1);
+        for i in 0..4 {
+            expected.set_colour(i, i, 2);
+        }
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+        assert_eq!(display.current_pixel, (3, 3));
+
+        let err = process_commands(&mut display, vec![3, 4, 0, 2]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #0 at offset 0: line to (4,0) outside 4x4"
+        );
+    }
+
+    #[test]
+    fn test_rects() {
+        let mut display = create_display(4, 4, 1);
+        process_commands(&mut display, vec![1, 3, 3, 4, 1, 1, 2, 5, 2, 2, 3]).unwrap();
+        let mut expected = Matrix::new(4, 4, 1);
+        for i in 1..=3 {
+            expected.set_colour(i, 1, 2);
+            expected.set_colour(1, i, 2);
+            expected.set_colour(i, 3, 2);
+            expected.set_colour(3, i, 2);
+        }
+        expected.set_colour(2, 2, 3);
+        expected.set_colour(2, 3, 3);
+        expected.set_colour(3, 2, 3);
+        expected.set_colour(3, 3, 3);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+        // Прямоугольники не двигают курсор
+        assert_eq!(display.current_pixel, (3, 3));
+    }
+
+    #[test]
+    fn test_circle_is_clipped() {
+        let mut display = create_display(3, 3, 1);
+        process_commands(&mut display, vec![1, 0, 0, 6, 2, 3]).unwrap();
+        let mut expected = Matrix::new(3, 3, 1);
+        expected.set_colour(2, 0, 3);
+        expected.set_colour(0, 2, 3);
+        expected.set_colour(2, 1, 3);
+        expected.set_colour(1, 2, 3);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+
+        process_commands(&mut display, vec![6, u64::MAX, 2]).unwrap();
+        let err = process_commands(&mut display, vec![6, 1, 4]).unwrap_err();
+        assert!(matches!(err, DisplayError::InvalidColour { colour: 4, .. }));
+    }
+
+    #[test]
+    fn test_relative_moves() {
+        let mut display = create_display(4, 4, 1);
+        // (1,1) -> (3,0) -> (2,2)
+        process_commands(&mut display, vec![1, 1, 1, 8, 4, 1, 8, 1, 4]).unwrap();
+        assert_eq!(display.current_pixel, (2, 2));
+        let err = process_commands(&mut display, vec![8, 6, 0]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #0 at offset 0: moveby by (3,0) from (2,2) outside 4x4"
+        );
+        assert_eq!(display.current_pixel, (2, 2));
+    }
+
+    #[test]
+    fn test_paint_next_scanline() {
+        let mut display = create_display(2, 2, 1);
+        display.set_edge_policy(EdgePolicy::Wrap);
+        process_commands(&mut display, vec![9, 2, 9, 3, 9, 3, 9, 2]).unwrap();
+        let mut expected = Matrix::new(2, 2, 2);
+        expected.set_colour(0, 1, 3);
+        expected.set_colour(1, 0, 3);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+        assert_eq!(display.current_pixel, (0, 0));
+
+        // С политикой Error шаг за край не рисует и возвращает ошибку
+        display.set_edge_policy(EdgePolicy::Error);
+        let err = process_commands(&mut display, vec![1, 1, 0, 9, 1]).unwrap_err();
+        assert!(matches!(
+            err,
+            DisplayError::StepOutOfBounds { by: (1, 0), .. }
+        ));
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+    }
+
+    #[test]
+    fn test_clamp_policy() {
+        let mut display = create_display(4, 4, 1);
+        display.set_edge_policy(EdgePolicy::Clamp);
+        process_commands(&mut display, vec![1, 9, 1, 8, 0, 3]).unwrap();
+        assert_eq!(display.current_pixel, (3, 0));
+        process_commands(&mut display, vec![9, 2, 9, 2]).unwrap();
+        assert_eq!(display.current_pixel, (3, 0));
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut display = create_display(3, 3, 1);
+        let overlay = display.add_layer(0);
+        assert_eq!(overlay, 1);
+        // Рисуем на верхнем слое, нижний не меняется
+        process_commands(&mut display, vec![10, 1, 1, 1, 1, 2, 3]).unwrap();
+        crate::assert_matrix_eq!(*display.layers()[0].matrix(), Matrix::new(3, 3, 1));
+
+        let mut expected = Matrix::new(3, 3, 1);
+        expected.set_colour(1, 1, 3);
+        crate::assert_matrix_eq!(display.flatten(), expected);
+
+        display.set_layer_visible(1, false);
+        crate::assert_matrix_eq!(display.flatten(), Matrix::new(3, 3, 1));
+        display.set_layer_visible(1, true);
+
+        // Второй слой поверх первого, затем меняем их местами
+        display.add_layer(0);
+        process_commands(&mut display, vec![10, 2, 5, 2, 2, 2]).unwrap();
+        let mut expected = Matrix::new(3, 3, 1);
+        expected.set_colour(1, 1, 2);
+        expected.set_colour(1, 2, 2);
+        expected.set_colour(2, 1, 2);
+        expected.set_colour(2, 2, 2);
+        crate::assert_matrix_eq!(display.flatten(), expected);
+        assert!(display.move_layer(2, 1));
+        assert_eq!(display.active_layer(), 1);
+        expected.set_colour(1, 1, 3);
+        crate::assert_matrix_eq!(display.flatten(), expected);
+
+        assert!(!display.remove_layer(0));
+        assert!(display.remove_layer(1));
+        assert_eq!(display.active_layer(), 0);
+        let err = process_commands(&mut display, vec![10, 5]).unwrap_err();
+        assert_eq!(err.to_string(), "command #0 at offset 0: invalid layer 5");
+    }
+
+    #[test]
+    fn test_transparent_colour_only_on_overlays() {
+        let mut display = create_display(2, 2, 1);
+        assert!(process_commands(&mut display, vec![2, 0]).is_err());
+        display.add_layer(0);
+        process_commands(&mut display, vec![10, 1, 2, 3, 2, 0]).unwrap();
+        assert!(display.undo());
+        assert_eq!(display.layers()[1].matrix().colour(0, 0), 3);
+        assert_eq!(display.flatten().colour(0, 0), 3);
+    }
+
+    #[test]
+    fn test_copy_and_stamp() {
+        let mut display = create_display(4, 4, 1);
+        // Спрайт 2x2: синий пиксель на зелёном фоне
+        process_commands(&mut display, vec![5, 1, 1, 2, 2, 3, 11, 2, 2]).unwrap();
+        let mut sprite = Matrix::new(2, 2, 2);
+        sprite.set_colour(0, 0, 3);
+        assert_eq!(display.sprites(), [sprite]);
+
+        // Копия у края, зелёный прозрачный
+        process_commands(&mut display, vec![1, 3, 3, 12, 0, 1, 2, 0, 13, 0, 2]).unwrap();
+        let mut expected = Matrix::new(4, 4, 1);
+        expected.set_colour(0, 0, 3);
+        expected.set_colour(0, 1, 2);
+        expected.set_colour(1, 0, 2);
+        expected.set_colour(1, 1, 2);
+        expected.set_colour(2, 0, 3);
+        expected.set_colour(3, 3, 3);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+
+        let err = process_commands(&mut display, vec![12, 1]).unwrap_err();
+        assert_eq!(err.to_string(), "command #0 at offset 0: invalid sprite 1");
+        // Цвета спрайта из API проверяются при рисовании
+        let bad = display.add_sprite(Matrix::new(1, 1, 9));
+        assert!(process_commands(&mut display, vec![12, bad as u64]).is_err());
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+    }
+
+    #[test]
+    fn test_transforms() {
+        let mut display = create_display(3, 3, 1);
+        display.add_layer(0);
+        process_commands(&mut display, vec![10, 1, 2, 3, 1, 2, 2]).unwrap();
+        let before = display.flatten();
+        // Поворот на 180 переносит курсор вместе с пикселем, все слои поворачиваются
+        process_commands(&mut display, vec![15]).unwrap();
+        assert_eq!(display.current_pixel, (0, 0));
+        crate::assert_matrix_eq!(display.flatten(), before.rotate180());
+        crate::assert_matrix_eq!(*display.layers()[0].matrix(), Matrix::new(3, 3, 1));
+        assert!(!display.undo());
+
+        process_commands(&mut display, vec![14, 16, 17, 17, 18, 18, 19, 19]).unwrap();
+        crate::assert_matrix_eq!(display.flatten(), before.rotate180());
+
+        process_commands(&mut display, vec![20, 6, 6]).unwrap();
+        crate::assert_matrix_eq!(display.flatten(), before.rotate180().resize(6, 6));
+        assert_eq!(display.boundaries, (6, 6));
+        process_commands(&mut display, vec![1, 5, 5, 2, 2]).unwrap();
+
+        let err = process_commands(&mut display, vec![20, 0, 4]).unwrap_err();
+        assert_eq!(err.to_string(), "command #0 at offset 0: invalid width 0");
+    }
+
+    #[test]
+    fn test_resize_limits() {
+        // Пустой дисплей растягивается до заливки фоном, прозрачные слои остаются прозрачными
+        let mut display = create_display(0, 3, 1);
+        display.add_layer(0);
+        display.set_origin(Origin::BottomLeft);
+        process_commands(&mut display, vec![20, 4, 4]).unwrap();
+        assert_eq!(display.boundaries, (4, 4));
+        assert_eq!(display.current_pixel, (0, 0));
+        crate::assert_matrix_eq!(*display.layers()[0].matrix(), Matrix::new(4, 4, 1));
+        crate::assert_matrix_eq!(*display.layers()[1].matrix(), Matrix::new(4, 4, 0));
+        process_commands(&mut display, vec![2, 3]).unwrap();
+        assert_eq!(display.flatten().colour(0, 3), 3);
+
+        let err =
+            process_commands(&mut display, vec![20, 4_000_000_000, 4_000_000_000]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #0 at offset 0: invalid area 16000000000000000000"
+        );
+        assert_eq!(display.boundaries, (4, 4));
+    }
+
+    #[test]
+    fn test_fill_on_empty_display() {
+        let mut display = create_display(0, 3, 1);
+        let err = process_commands(&mut display, vec![7, 4, 2]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #0 at offset 0: fill to (0,0) outside 0x3"
+        );
+        let program = program::Program::compile(&[7, 4, 2]).unwrap();
+        assert!(program.verify(&display).is_err());
+    }
+
+    #[test]
+    fn test_non_square_display() {
+        let mut display = create_display(5, 2, 1);
+        process_commands(&mut display, vec![1, 4, 1, 2, 3, 1, 0, 0, 3, 2, 0, 2]).unwrap();
+        assert_eq!(
+            display.matrix().rows().collect::<Vec<_>>(),
+            vec![&[2, 2, 2, 1, 1][..], &[1, 1, 1, 1, 3][..]]
+        );
+        // x == width и y == height уже за краем
+        let err = process_commands(&mut display, vec![1, 5, 0]).unwrap_err();
+        assert!(matches!(err, DisplayError::OutOfBounds { x: 5, y: 0, .. }));
+        let err = process_commands(&mut display, vec![1, 0, 2]).unwrap_err();
+        assert!(matches!(err, DisplayError::OutOfBounds { x: 0, y: 2, .. }));
+        let err = process_commands(&mut display, vec![1, 4, 0, 9, 3]).unwrap_err();
+        assert!(matches!(err, DisplayError::StepOutOfBounds { .. }));
+
+        // Поворот 5x2 даёт 2x5, курсор остаётся на своём пикселе
+        process_commands(&mut display, vec![1, 4, 1, 14]).unwrap();
+        assert_eq!(display.boundaries, (2, 5));
+        assert_eq!(display.current_pixel, (0, 4));
+        assert_eq!(display.matrix().colour(0, 4), 3);
+        process_commands(&mut display, vec![20, 4, 10]).unwrap();
+        assert_eq!(display.current_pixel, (0, 8));
+        assert_eq!(display.matrix().colour(0, 8), 3);
+    }
+
+    #[test]
+    fn test_bottom_left_origin() {
+        let mut display = create_display(4, 3, 1);
+        display.set_origin(Origin::BottomLeft);
+        // (0, 0) - левый нижний угол, y растёт вверх. Курсор остался на
+        // левом верхнем пикселе, поэтому сначала переходим в начало координат
+        assert_eq!(display.current_pixel, (0, 2));
+        process_commands(&mut display, vec![1, 0, 0, 2, 3, 1, 3, 2, 2, 2]).unwrap();
+        assert_eq!(
+            display.matrix().rows().collect::<Vec<_>>(),
+            vec![&[1, 1, 1, 2][..], &[1, 1, 1, 1][..], &[3, 1, 1, 1][..]]
+        );
+        assert!(display.undo());
+        assert_eq!(display.matrix().colour(3, 0), 1);
+        assert!(display.redo());
+
+        // Копия и штамп сохраняют вид спрайта
+        process_commands(&mut display, vec![1, 0, 0, 11, 1, 3, 1, 1, 0, 12, 0]).unwrap();
+        let mut sprite = Matrix::new(1, 3, 1);
+        sprite.set_colour(0, 2, 3);
+        assert_eq!(display.sprites(), [sprite]);
+        assert_eq!(display.matrix().colour(1, 2), 3);
+
+        // Смена начала координат не сдвигает курсор с пикселя
+        process_commands(&mut display, vec![1, 3, 2]).unwrap();
+        display.set_origin(Origin::TopLeft);
+        assert_eq!(display.current_pixel, (3, 0));
+        display.set_origin(Origin::BottomLeft);
+        process_commands(&mut display, vec![18]).unwrap();
+        assert_eq!(display.current_pixel, (3, 0));
+        assert_eq!(display.matrix().colour(3, 2), 2);
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut display = create_display(4, 4, 1);
+        // Контур 3x3 в углу, затем заливка снаружи
+        process_commands(&mut display, vec![1, 2, 2, 4, 0, 0, 2, 1, 3, 3, 7, 4, 3]).unwrap();
+        let mut expected = Matrix::new(4, 4, 3);
+        for i in 0..3 {
+            expected.set_colour(i, 0, 2);
+            expected.set_colour(0, i, 2);
+            expected.set_colour(i, 2, 2);
+            expected.set_colour(2, i, 2);
+        }
+        expected.set_colour(1, 1, 1);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+
+        let err = process_commands(&mut display, vec![7, 6, 3]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command #0 at offset 0: invalid connectivity 6"
+        );
+    }
+
+    #[test]
+    fn test_custom_palette() {
+        let palette = palette::Palette::xterm256();
+        let mut display = create_display_with_palette(4, 4, 0, palette);
+        process_commands(&mut display, vec![1, 1, 1, 2, 208, 2, 0]).unwrap();
+        let err = process_commands(&mut display, vec![2, 256]).unwrap_err();
+        assert!(matches!(
+            err,
+            DisplayError::InvalidColour { colour: 256, .. }
+        ));
+
+        let palette = palette::Palette::parse("5 orange #ff8000").unwrap();
+        let mut display = create_display_with_palette(4, 4, 5, palette);
+        let err = process_commands(&mut display, vec![2, 1]).unwrap_err();
+        assert!(matches!(err, DisplayError::InvalidColour { colour: 1, .. }));
+    }
+
+    #[test]
+    fn test_svg_snapshot() {
+        let mut display = create_display(2, 2, 1);
+        process_commands(&mut display, vec![1, 1, 1, 2, 2]).unwrap();
+        let svg = display.to_svg(8, false);
+        assert!(svg.contains(r##"<rect x="8" y="8" width="8" height="8" fill="#00ff00"/>"##));
+        assert!(svg.contains(r#"<rect x="8" y="8" width="8" height="8" fill="none""#));
+        assert!(!svg.contains("<line"));
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut display = create_display(4, 4, 1);
+        process_commands(&mut display, vec![1, 1, 1, 2, 2, 5, 3, 3, 3]).unwrap();
+        let mut painted = Matrix::new(4, 4, 1);
+        painted.set_colour(1, 1, 2);
+
+        assert!(display.undo());
+        crate::assert_matrix_eq!(*display.matrix(), painted);
+        assert_eq!(display.current_pixel, (1, 1));
+        assert!(display.undo());
+        assert!(display.undo());
+        crate::assert_matrix_eq!(*display.matrix(), Matrix::new(4, 4, 1));
+        assert_eq!(display.current_pixel, (0, 0));
+        assert!(!display.undo());
+
+        assert!(display.redo());
+        assert!(display.redo());
+        crate::assert_matrix_eq!(*display.matrix(), painted);
+        assert_eq!(display.current_pixel, (1, 1));
+
+        // Новая команда отбрасывает отменённые
+        process_commands(&mut display, vec![2, 3]).unwrap();
+        assert!(!display.redo());
+    }
+
+    #[test]
+    fn test_undo_to_checkpoint() {
+        let mut display = create_display(4, 4, 1);
+        process_commands(&mut display, vec![1, 1, 1, 2, 2]).unwrap();
+        let checkpoint = display.checkpoint();
+        let mut expected = Matrix::new(4, 4, 1);
+        expected.set_colour(1, 1, 2);
+
+        // Ошибочная пачка: первые команды применены, потом ошибка
+        assert!(process_commands(&mut display, vec![5, 3, 3, 3, 6, 2, 9]).is_err());
+        assert_ne!(*display.matrix(), expected);
+        assert!(display.undo_to_checkpoint(checkpoint));
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+        assert_eq!(display.current_pixel, (1, 1));
+        assert!(display.undo_to_checkpoint(checkpoint));
+
+        // Отметка в отменённой части истории недостижима
+        process_commands(&mut display, vec![2, 3]).unwrap();
+        let later = display.checkpoint();
+        display.undo();
+        assert!(!display.undo_to_checkpoint(later));
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let mut display = create_display(4, 4, 1);
+        let start = display.checkpoint();
+        display.set_history_limit(2);
+        process_commands(&mut display, vec![2, 2, 2, 3, 2, 2]).unwrap();
+        assert!(!display.undo_to_checkpoint(start));
+        assert!(display.undo());
+        assert!(display.undo());
+        assert!(!display.undo());
+        let mut expected = Matrix::new(4, 4, 1);
+        expected.set_colour(0, 0, 2);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+
+        display.set_history_limit(0);
+        process_commands(&mut display, vec![2, 3]).unwrap();
+        assert!(!display.undo());
+    }
+
+    #[test]
+    fn test_history_change_limit() {
+        let mut display = create_display(4, 4, 1);
+        display.set_history_change_limit(10);
+        // Два отрезка по 4 пикселя помещаются, третий вытесняет первый
+        let row = |y: u64, colour: u64| vec![1, 0, y, 3, 3, y, colour];
+        for y in 0..3 {
+            process_commands(&mut display, row(y, 2)).unwrap();
+        }
+        // От каждой строки остаются MOVE и LINE, от первой - ничего
+        let mut undos = 0;
+        while display.undo() {
+            undos += 1;
+        }
+        assert_eq!(undos, 4);
+        assert_eq!(display.matrix().colour(0, 0), 2);
+        assert_eq!(display.matrix().colour(0, 1), 1);
+
+        // Закраска 12 пикселей больше лимита: она не сохраняется и закрывает
+        // путь к более старым командам
+        process_commands(&mut display, row(3, 3)).unwrap();
+        process_commands(&mut display, vec![1, 0, 0, 5, 3, 3, 3]).unwrap();
+        assert!(!display.undo());
+        assert_eq!(display.matrix().colour(0, 0), 3);
+    }
+
+    #[test]
+    fn test_other_case() {
+        let mut display = create_display(5, 5, 3);
+        process_commands(&mut display, vec![1, 3, 2, 2, 1]).unwrap();
+        let mut expected = Matrix::new(5, 5, 3);
+        expected.set_colour(3, 2, 1);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+        println!("Other case: ");
+        display.matrix().display();
+    }
+    #[test]
+    fn test_complex_case() {
+        let mut display = create_display(5, 5, 3);
+        process_commands(&mut display, vec![1, 3, 2, 2, 1, 1, 2, 3, 2, 2]).unwrap();
+        let mut expected = Matrix::new(5, 5, 3);
+        expected.set_colour(3, 2, 1);
+        expected.set_colour(2, 3, 2);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+        println!("Complex case: ");
+        display.matrix().display();
+    }
+    #[test]
+    fn test_more_complex_case() {
+        let mut display = create_display(6, 6, 3);
+        process_commands(
+            &mut display,
+            vec![1, 3, 3, 2, 1, 1, 4, 4, 2, 2, 1, 5, 5, 2, 1],
+        )
+        .unwrap();
+        let mut expected = Matrix::new(6, 6, 3);
+        expected.set_colour(3, 3, 1);
+        expected.set_colour(4, 4, 2);
+        expected.set_colour(5, 5, 1);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+        println!("More complex case: ");
+        display.matrix().display();
+    }
+}
@@ -1,188 +1,68 @@
-// Вам нужно реализовать программу обработки команд для дисплея.
+use std::env;
+use std::io::{self, IsTerminal};
+use std::process;
 
-// На вход пользователь подает:
-// * 2 числа: размер дисплея
-// * 1 число: цвет дисплея по-умолчанию (1 - красный, 2 - зеленый, 3 - синий)
-// * Последовательность команд: набор чисел.
-//
-// Дисплей поддерживает следующие команды:
-// * 1 x y - переместить курсор в позицию x y
-// * 2 colour - перекрасить пиксель в цвет colour
-//
-// Пример входных данных:
-// 4 4
-// 1
-// 1 2 2 2 3
-// В результате пиксель по позиции (2,2) будет перекрашен в синий цвет
+use display::cli::{self, parse_colour};
+use display::palette::Palette;
+use display::repl::Repl;
 
-// Обновлять состояние дисплея нужно через метод matrix.set_colour(pos_x, pos_y, colour)
-
-// Важно! Обязательна проверка на ошибки. Если пользователь просит переместиться на пиксель за пределами дисплея или ввел неправильный цвет, то вам нужно кинуть панику!
-
-use std::io;
-mod matrix;
-use matrix::Matrix;
-
-struct Display {
-    // можете добавить сюда любые дополнительные поля
-    current_pixel: (u64, u64),
-    boundaries: (u32, u32),
-    matrix: Matrix,
-}
-
-fn create_display(max_width: u32, max_height: u32, default_colour: u8) -> Display {
-    // ваш код сюда
-    Display {
-        current_pixel: (0, 0),
-        boundaries: (max_width, max_height),
-        matrix: Matrix::new(max_width, max_height, default_colour),
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        interactive();
+        return;
     }
-}
-
-fn process_commands(display: &mut Display, input: Vec<u64>) {
-    let mut offset = 0;
-    for (idx, command) in input.iter().enumerate() {
-        //        if idx < offset {
-        //            continue;
-        //        }
-
-        while idx >= offset {
-            match command {
-                1 => {
-                    let x = input[idx + 1];
-                    let y = input[idx + 2];
-                    if x > display.boundaries.0 as u64 || y > display.boundaries.1 as u64 {
-                        panic!("Out of display boundaries");
-                    }
-                    display.current_pixel = (x, y);
-                    offset += 3;
-                }
-                2 => {
-                    let colour = input[idx + 1] as u8;
-                    if !(1..=3).contains(&colour) {
-                        panic!("No such colour");
-                    }
-                    display.matrix.set_colour(
-                        display.current_pixel.0,
-                        display.current_pixel.1,
-                        colour,
-                    );
-                    offset += 2;
-                }
-                _ => {
-                    panic!("No such command");
-                }
-            }
+    let options = match cli::parse_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {err}\n\n{}", cli::USAGE);
+            process::exit(err.exit_code());
         }
+    };
+    if let Err(err) = cli::run(&options, io::stdin().lock(), &mut io::stdout().lock()) {
+        eprintln!("error: {err}");
+        process::exit(err.exit_code());
     }
 }
 
-// код ниже трогать не нужно, можете просто посмотреть его
-
-// тесты
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_happy_case() {
-        let mut display = create_display(4, 4, 1);
-        process_commands(&mut display, vec![1, 2, 2, 2, 3]);
-        let mut expected = Matrix::new(4, 4, 1);
-        expected.set_colour(2, 2, 3);
-        assert_eq!(display.matrix, expected);
-    }
-
-    #[test]
-    #[should_panic]
-    fn test_error() {
-        let mut display = create_display(4, 4, 1);
-        process_commands(&mut display, vec![1, 5, 5, 2, 3]);
-    }
-
-    #[test]
-    #[should_panic]
-    fn test_error_invalid_colour() {
-        let mut display = create_display(4, 4, 1);
-        process_commands(&mut display, vec![1, 2, 2, 2, 5]);
-    }
-
-    #[test]
-    #[should_panic]
-    fn test_error_invalid_command() {
-        let mut display = create_display(4, 4, 1);
-        process_commands(&mut display, vec![1, 2, 2, 3, 5]);
+// Диалог с подсказками. Если ввод не с терминала, подсказки не печатаются
+fn interactive() {
+    let prompts = io::stdin().is_terminal();
+    if prompts {
+        println!("Введите размеры дисплея (ширина высота):");
     }
-
-    #[test]
-    fn test_other_case() {
-        let mut display = create_display(5, 5, 3);
-        process_commands(&mut display, vec![1, 3, 2, 2, 1]);
-        let mut expected = Matrix::new(5, 5, 3);
-        expected.set_colour(3, 2, 1);
-        assert_eq!(display.matrix, expected);
-        println!("Other case: ");
-        display.matrix.display();
-    }
-    #[test]
-    fn test_complex_case() {
-        let mut display = create_display(5, 5, 3);
-        process_commands(&mut display, vec![1, 3, 2, 2, 1, 1, 2, 3, 2, 2]);
-        let mut expected = Matrix::new(5, 5, 3);
-        expected.set_colour(3, 2, 1);
-        expected.set_colour(2, 3, 2);
-        assert_eq!(display.matrix, expected);
-        println!("Complex case: ");
-        display.matrix.display();
-    }
-    #[test]
-    fn test_more_complex_case() {
-        let mut display = create_display(6, 6, 3);
-        process_commands(
-            &mut display,
-            vec![1, 3, 3, 2, 1, 1, 4, 4, 2, 2, 1, 5, 5, 2, 1],
-        );
-        let mut expected = Matrix::new(6, 6, 3);
-        expected.set_colour(3, 3, 1);
-        expected.set_colour(4, 4, 2);
-        expected.set_colour(5, 5, 1);
-        assert_eq!(display.matrix, expected);
-        println!("More complex case: ");
-        display.matrix.display();
-    }
-}
-
-fn main() {
-    println!("Введите размеры дисплея (ширина высота):");
     let mut input = String::new();
     io::stdin().read_line(&mut input).unwrap();
     let (width, height) = parse_dimensions(&input);
 
-    println!("Введите стандартный цвет дисплея (1 - красный, 2 - зеленый, 3 - синий):");
-    input.clear();
-    io::stdin().read_line(&mut input).unwrap();
-    let default_colour = match input.trim() {
-        "1" => 1, // Красный
-        "2" => 2, // Зеленый
-        "3" => 3, // Синий
-        _ => panic!("Неверный ввод цвета. Ожидалось 1, 2 или 3."),
-    };
-
-    // Создаём дисплей и заполняем его стандартным цветом
-    let mut display = create_display(width, height, default_colour);
-
-    // Ввод действий
-    println!("Введите строку с действиями:");
+    let palette = Palette::default();
+    let colours: Vec<String> = palette
+        .iter()
+        .map(|entry| format!("{} - {}", entry.index, entry.name))
+        .collect();
+    if prompts {
+        println!("Введите стандартный цвет дисплея ({}):", colours.join(", "));
+    }
     input.clear();
     io::stdin().read_line(&mut input).unwrap();
-    let commands = input
-        .split_whitespace()
-        .map(|x| x.parse().unwrap())
-        .collect();
-    // Отображение дисплея
-    process_commands(&mut display, commands);
-
-    display.matrix.display();
+    let default_colour = parse_colour(input.trim(), &palette).unwrap_or_else(|| {
+        panic!(
+            "Неверный ввод цвета. Ожидалось одно из: {}.",
+            colours.join(", ")
+        )
+    });
+
+    // Дисплей живёт, пока пользователь вводит команды
+    if prompts {
+        println!("Вводите команды построчно, :help - список команд, :quit - выход");
+    }
+    let mut repl = Repl::new(width, height, default_colour, palette).with_prompt(prompts);
+    repl.run(io::stdin().lock(), &mut io::stdout())
+        .expect("failed to write to stdout");
 }
 
 fn parse_dimensions(input: &str) -> (u32, u32) {
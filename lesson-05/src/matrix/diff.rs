@@ -0,0 +1,152 @@
+// Попиксельное сравнение двух матриц и понятный отчёт о различиях для тестов.
+
+use std::fmt::Write;
+
+use super::Matrix;
+
+/// Пиксель, цвет которого отличается. Координаты те же, что у Matrix::colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelChange {
+    pub x: u64,
+    pub y: u64,
+    pub before: u8,
+    pub after: u8,
+}
+
+// Больше различий в отчёт не попадает, остаток только считается
+const REPORT_LIMIT: usize = 16;
+
+impl Matrix {
+    /// Чем other отличается от self, по строкам сверху вниз. Матрицы разного
+    /// размера сравниваются по общей части
+    pub fn diff(&self, other: &Matrix) -> Vec<PixelChange> {
+        let mut changes = Vec::new();
+        for (y, (row, other_row)) in self.rows().zip(other.rows()).enumerate() {
+            for (x, (&before, &after)) in row.iter().zip(other_row).enumerate() {
+                if before != after {
+                    changes.push(PixelChange {
+                        x: x as u64,
+                        y: y as u64,
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+        changes
+    }
+}
+
+/// Описание различий left и right для сообщения об ошибке, None если матрицы равны
+pub fn mismatch_report(left: &Matrix, right: &Matrix) -> Option<String> {
+    if left == right {
+        return None;
+    }
+    let mut report = String::new();
+    let (left_size, right_size) = (
+        (left.width(), left.height()),
+        (right.width(), right.height()),
+    );
+    if left_size != right_size {
+        let _ = write!(
+            report,
+            "sizes differ: left is {}x{}, right is {}x{}",
+            left_size.0, left_size.1, right_size.0, right_size.1
+        );
+        return Some(report);
+    }
+    let changes = left.diff(right);
+    let _ = write!(report, "{} pixel(s) differ", changes.len());
+    for change in changes.iter().take(REPORT_LIMIT) {
+        let _ = write!(
+            report,
+            "\n  ({}, {}): left {}, right {}",
+            change.x, change.y, change.before, change.after
+        );
+    }
+    if changes.len() > REPORT_LIMIT {
+        let _ = write!(report, "\n  ... and {} more", changes.len() - REPORT_LIMIT);
+    }
+    Some(report)
+}
+
+/// Как assert_eq! для Matrix, но вместо двух дампов показывает отличающиеся пиксели
+#[macro_export]
+macro_rules! assert_matrix_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        if let Some(report) = $crate::matrix::mismatch_report(&$left, &$right) {
+            panic!("assertion `left == right` failed for matrices: {report}");
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let left = Matrix::new(3, 3, 1);
+        let mut right = left.clone();
+        right.set_colour(2, 1, 3);
+        right.set_colour(0, 0, 2);
+        assert_eq!(
+            left.diff(&right),
+            vec![
+                PixelChange {
+                    x: 0,
+                    y: 0,
+                    before: 1,
+                    after: 2
+                },
+                PixelChange {
+                    x: 2,
+                    y: 1,
+                    before: 1,
+                    after: 3
+                },
+            ]
+        );
+        assert!(left.diff(&left).is_empty());
+
+        // На широкой матрице x - столбец
+        let mut wide = Matrix::new(4, 1, 1);
+        wide.set_colour(3, 0, 2);
+        assert_eq!(
+            Matrix::new(4, 1, 1).diff(&wide),
+            vec![PixelChange {
+                x: 3,
+                y: 0,
+                before: 1,
+                after: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_mismatch_report() {
+        let left = Matrix::new(20, 20, 1);
+        assert_eq!(mismatch_report(&left, &left), None);
+        assert_eq!(
+            mismatch_report(&left, &Matrix::new(2, 3, 1)).unwrap(),
+            "sizes differ: left is 20x20, right is 2x3"
+        );
+        let mut right = left.clone();
+        right.set_colour(4, 5, 2);
+        assert_eq!(
+            mismatch_report(&left, &right).unwrap(),
+            "1 pixel(s) differ\n  (4, 5): left 1, right 2"
+        );
+        let report = mismatch_report(&left, &Matrix::new(20, 20, 3)).unwrap();
+        assert!(report.starts_with("400 pixel(s) differ\n  (0, 0): left 1, right 3"));
+        assert!(report.ends_with("\n  ... and 384 more"));
+    }
+
+    #[test]
+    #[should_panic(expected = "(1, 1): left 1, right 3")]
+    fn test_assert_matrix_eq() {
+        let mut right = Matrix::new(2, 2, 1);
+        right.set_colour(1, 1, 3);
+        crate::assert_matrix_eq!(Matrix::new(2, 2, 1), right);
+    }
+}
@@ -1,30 +1,460 @@
-#[derive(PartialEq, Debug)]
-pub struct Matrix(Vec<Vec<u8>>);
+mod diff;
+mod netpbm;
+mod snapshot;
+mod svg;
+mod transform;
 
-fn color_to_char(color: u8) -> char {
-    match color {
-        1 => '\u{1F534}', // Красный кружок
-        2 => '\u{1F7E2}', // Зелёный кружок
-        3 => '\u{1F535}', // Синий кружок
-        _ => ' ',
+pub use diff::{mismatch_report, PixelChange};
+pub use netpbm::{NetpbmError, NetpbmFormat};
+pub use snapshot::{Snapshot, SnapshotError, SNAPSHOT_VERSION};
+pub use svg::SvgOptions;
+pub use transform::Transform;
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::palette::Palette;
+use crate::render::{EmojiRenderer, Renderer};
+
+/// Больше пикселей Matrix не получает ни из файла, ни через RESIZE,
+/// чтобы испорченный заголовок или команда не съели всю память
+pub const MAX_PIXELS: u64 = 1 << 30;
+
+/// Какие соседи пикселя считаются связанными при заливке
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Только по горизонтали и вертикали
+    Four,
+    /// Ещё и по диагоналям
+    Eight,
+}
+
+impl Connectivity {
+    pub(crate) fn neighbours(self) -> &'static [(i64, i64)] {
+        match self {
+            Connectivity::Four => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+            Connectivity::Eight => &[
+                (1, 0),
+                (-1, 0),
+                (0, 1),
+                (0, -1),
+                (1, 1),
+                (1, -1),
+                (-1, 1),
+                (-1, -1),
+            ],
+        }
+    }
+}
+
+/// Прямоугольник пикселей. Во всех методах x - номер столбца слева направо
+/// (от 0 до width), y - номер строки сверху вниз (от 0 до height).
+/// Пиксели лежат одним массивом по строкам сверху вниз, строка y занимает
+/// pixels[y * width..(y + 1) * width].
+/// Для serde матрица - массив строк сверху вниз, а матрица без строк -
+/// `{"width": w}`, чтобы не потерять ширину
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "Rows", into = "Rows")
+)]
+pub struct Matrix {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+/// Строки разной длины, из них матрицу не собрать
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RaggedRows {
+    pub row: usize,
+    pub expected: usize,
+    pub found: usize,
+}
+
+impl fmt::Display for RaggedRows {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "row {} has {} pixels, expected {}",
+            self.row, self.found, self.expected
+        )
+    }
+}
+
+impl Error for RaggedRows {}
+
+impl TryFrom<Vec<Vec<u8>>> for Matrix {
+    type Error = RaggedRows;
+
+    fn try_from(rows: Vec<Vec<u8>>) -> Result<Self, Self::Error> {
+        let expected = rows.first().map_or(0, Vec::len);
+        if let Some((row, cells)) = rows.iter().enumerate().find(|(_, r)| r.len() != expected) {
+            return Err(RaggedRows {
+                row,
+                expected,
+                found: cells.len(),
+            });
+        }
+        Ok(Matrix::from_pixels(expected, rows.len(), rows.concat()))
+    }
+}
+
+impl From<Matrix> for Vec<Vec<u8>> {
+    fn from(matrix: Matrix) -> Self {
+        matrix.rows().map(<[u8]>::to_vec).collect()
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum Rows {
+    Rows(Vec<Vec<u8>>),
+    Empty { width: usize },
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<Rows> for Matrix {
+    type Error = RaggedRows;
+
+    fn try_from(rows: Rows) -> Result<Self, Self::Error> {
+        match rows {
+            Rows::Rows(rows) => Matrix::try_from(rows),
+            Rows::Empty { width } => Ok(Matrix::from_pixels(width, 0, Vec::new())),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Matrix> for Rows {
+    fn from(matrix: Matrix) -> Self {
+        if matrix.height() == 0 && matrix.width() > 0 {
+            Rows::Empty {
+                width: matrix.width(),
+            }
+        } else {
+            Rows::Rows(matrix.into())
+        }
     }
 }
 
 impl Matrix {
     pub fn new(width: u32, height: u32, default_color: u8) -> Self {
-        Self(vec![vec![default_color; width as usize]; height as usize])
+        let (width, height) = (width as usize, height as usize);
+        Self::from_pixels(width, height, vec![default_color; width * height])
+    }
+
+    // Матрица из уже разложенных по строкам пикселей
+    pub(crate) fn from_pixels(width: usize, height: usize, pixels: Vec<u8>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "pixels do not fill {width}x{height}"
+        );
+        Self {
+            width,
+            height,
+            pixels,
+        }
     }
 
     pub fn display(&self) {
-        for row in &self.0 {
-            for &cell in row {
-                print!("{}", color_to_char(cell));
-            }
-            println!();
+        self.display_with_palette(&Palette::default());
+    }
+
+    pub fn display_with_palette(&self, palette: &Palette) {
+        self.render(&EmojiRenderer, palette, &mut io::stdout().lock())
+            .expect("failed to write to stdout");
+    }
+
+    /// Выводит матрицу выбранным способом в любой поток
+    pub fn render(
+        &self,
+        renderer: &dyn Renderer,
+        palette: &Palette,
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        renderer.render(self, palette, out)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Строка y, паникует за нижним краем
+    pub fn row(&self, y: usize) -> &[u8] {
+        assert!(y < self.height, "row {y} is outside height {}", self.height);
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        assert!(y < self.height, "row {y} is outside height {}", self.height);
+        &mut self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    /// Строки матрицы сверху вниз
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.height).map(|y| self.row(y))
+    }
+
+    /// Все пиксели (x, y, цвет) по строкам сверху вниз
+    pub fn pixels(&self) -> impl Iterator<Item = (u64, u64, u8)> + '_ {
+        let width = self.width.max(1);
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(move |(index, &colour)| ((index % width) as u64, (index / width) as u64, colour))
+    }
+
+    /// То же, что pixels, но цвета можно менять на месте
+    pub fn pixels_mut(&mut self) -> impl Iterator<Item = (u64, u64, &mut u8)> + '_ {
+        let width = self.width.max(1);
+        self.pixels
+            .iter_mut()
+            .enumerate()
+            .map(move |(index, colour)| ((index % width) as u64, (index / width) as u64, colour))
+    }
+
+    /// Цвет пикселя или None, если (x, y) за краем
+    pub fn get(&self, x: u64, y: u64) -> Option<u8> {
+        if x >= self.width as u64 || y >= self.height as u64 {
+            return None;
         }
+        Some(self.pixels[y as usize * self.width + x as usize])
     }
 
     pub fn set_colour(&mut self, x: u64, y: u64, colour: u8) {
-        self.0[x as usize][y as usize] = colour;
+        self.row_mut(y as usize)[x as usize] = colour;
+    }
+
+    pub fn colour(&self, x: u64, y: u64) -> u8 {
+        self.row(y as usize)[x as usize]
+    }
+
+    /// Копирует поверх себя пиксели top того же размера, кроме цвета transparent
+    pub fn overlay(&mut self, top: &Matrix, transparent: Option<u8>) {
+        for (cell, &top_cell) in self.pixels.iter_mut().zip(&top.pixels) {
+            if Some(top_cell) != transparent {
+                *cell = top_cell;
+            }
+        }
+    }
+
+    /// Копия прямоугольника w x h с углом в (x, y). Часть за краем матрицы
+    /// отбрасывается, поэтому копия может оказаться меньше или пустой
+    pub fn copy_region(&self, x: u64, y: u64, w: u64, h: u64) -> Matrix {
+        let (width, height) = (self.width() as u64, self.height() as u64);
+        let (x, y) = (x.min(width), y.min(height));
+        let (x_end, y_end) = (
+            x.saturating_add(w).min(width),
+            y.saturating_add(h).min(height),
+        );
+        let (x, x_end) = (x as usize, x_end as usize);
+        let mut pixels = Vec::with_capacity((x_end - x) * (y_end - y) as usize);
+        for y in y as usize..y_end as usize {
+            pixels.extend_from_slice(&self.row(y)[x..x_end]);
+        }
+        Matrix::from_pixels(x_end - x, (y_end - y) as usize, pixels)
+    }
+
+    /// Рисует sprite поверх себя с углом в (x, y), угол может быть и за краем.
+    /// Пиксели за краем и пиксели цвета transparent пропускаются
+    pub fn blit(&mut self, sprite: &Matrix, x: i64, y: i64, transparent: Option<u8>) {
+        let (width, height) = (self.width() as i64, self.height() as i64);
+        for (dx, dy, colour) in sprite.pixels() {
+            let (tx, ty) = (x + dx as i64, y + dy as i64);
+            if Some(colour) != transparent && (0..width).contains(&tx) && (0..height).contains(&ty)
+            {
+                self.set_colour(tx as u64, ty as u64, colour);
+            }
+        }
+    }
+
+    /// Перекрашивает связную область одного цвета, начиная с (x, y)
+    pub fn flood_fill(&mut self, x: u64, y: u64, colour: u8, connectivity: Connectivity) {
+        if self.colour(x, y) == colour {
+            return;
+        }
+        for (x, y) in self.region(x, y, connectivity) {
+            self.set_colour(x, y, colour);
+        }
+    }
+
+    /// Пиксели связной области того же цвета, что и (x, y). Обход идёт
+    /// через явный стек, поэтому размер области не ограничен стеком вызовов
+    pub fn region(&self, x: u64, y: u64, connectivity: Connectivity) -> Vec<(u64, u64)> {
+        let (width, height) = (self.width(), self.height());
+        let target = self.colour(x, y);
+        let mut visited = vec![false; width * height];
+        let mut stack = vec![(x, y)];
+        let mut region = Vec::new();
+        visited[y as usize * width + x as usize] = true;
+        while let Some((x, y)) = stack.pop() {
+            region.push((x, y));
+            for &(dx, dy) in connectivity.neighbours() {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx < 0 || ny < 0 || nx as usize >= width || ny as usize >= height {
+                    continue;
+                }
+                let index = ny as usize * width + nx as usize;
+                if !visited[index] && self.colour(nx as u64, ny as u64) == target {
+                    visited[index] = true;
+                    stack.push((nx as u64, ny as u64));
+                }
+            }
+        }
+        region
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_rows() {
+        let matrix = Matrix::try_from(vec![vec![1, 2], vec![3, 4]]).unwrap();
+        assert_eq!(matrix.colour(0, 1), 3);
+        assert_eq!(Vec::from(matrix), vec![vec![1, 2], vec![3, 4]]);
+        let err = Matrix::try_from(vec![vec![1, 2], vec![3]]).unwrap_err();
+        assert_eq!(err.to_string(), "row 1 has 1 pixels, expected 2");
+    }
+
+    #[test]
+    fn test_coordinates_on_wide_matrix() {
+        // x - столбец, y - строка
+        let mut matrix = Matrix::new(5, 2, 0);
+        matrix.set_colour(4, 1, 7);
+        matrix.set_colour(1, 0, 3);
+        assert_eq!((matrix.width(), matrix.height()), (5, 2));
+        assert_eq!(
+            matrix.rows().collect::<Vec<_>>(),
+            vec![&[0, 3, 0, 0, 0][..], &[0, 0, 0, 0, 7][..]]
+        );
+        assert_eq!(matrix.colour(4, 1), 7);
+        let cells: Vec<_> = matrix.pixels().filter(|&(_, _, c)| c != 0).collect();
+        assert_eq!(cells, vec![(1, 0, 3), (4, 1, 7)]);
+
+        let region = matrix.copy_region(3, 0, 5, 5);
+        assert_eq!(Vec::from(region), vec![vec![0, 0], vec![0, 7]]);
+
+        let mut tall = Matrix::new(2, 5, 0);
+        tall.blit(&matrix.copy_region(0, 0, 2, 1), 0, 4, None);
+        assert_eq!(tall.colour(1, 4), 3);
+
+        // Заливка не выходит за 5 столбцов и 2 строки
+        let mut strip = Matrix::new(5, 2, 1);
+        strip.set_colour(2, 0, 2);
+        strip.set_colour(2, 1, 2);
+        let mut filled = strip.clone();
+        filled.flood_fill(0, 1, 3, Connectivity::Four);
+        assert_eq!(
+            Vec::from(filled),
+            vec![vec![3, 3, 2, 1, 1], vec![3, 3, 2, 1, 1]]
+        );
+    }
+
+    #[test]
+    fn test_flat_rows() {
+        let mut matrix = Matrix::new(3, 2, 0);
+        matrix.row_mut(1).copy_from_slice(&[4, 5, 6]);
+        assert_eq!(matrix.row(0), &[0, 0, 0]);
+        assert_eq!(matrix.row(1), &[4, 5, 6]);
+        assert_eq!(matrix.get(2, 1), Some(6));
+        assert_eq!(matrix.get(3, 0), None);
+        assert_eq!(matrix.get(0, 2), None);
+
+        for (x, y, colour) in matrix.pixels_mut() {
+            *colour += (x + 10 * y) as u8;
+        }
+        assert_eq!(Vec::from(matrix), vec![vec![0, 1, 2], vec![14, 16, 18]]);
+
+        // Матрица без столбцов всё равно помнит высоту
+        let empty = Matrix::new(0, 3, 0);
+        assert_eq!((empty.width(), empty.height()), (0, 3));
+        assert_eq!(empty.rows().count(), 3);
+        assert_eq!(empty.pixels().count(), 0);
+    }
+
+    #[test]
+    fn test_copy_region_is_clipped() {
+        let mut matrix = Matrix::new(4, 4, 0);
+        matrix.set_colour(2, 2, 1);
+        matrix.set_colour(3, 3, 2);
+        let mut expected = Matrix::new(2, 2, 0);
+        expected.set_colour(0, 0, 1);
+        expected.set_colour(1, 1, 2);
+        crate::assert_matrix_eq!(matrix.copy_region(2, 2, 5, 5), expected);
+        assert_eq!(matrix.copy_region(4, 0, 1, 1).width(), 0);
+        assert_eq!(matrix.copy_region(1, 1, 0, 2).width(), 0);
+    }
+
+    #[test]
+    fn test_blit() {
+        let mut sprite = Matrix::new(2, 2, 3);
+        sprite.set_colour(0, 0, 0);
+        let mut matrix = Matrix::new(3, 3, 1);
+        // Спрайт свисает за левый верхний угол, прозрачный угол не рисуется
+        matrix.blit(&sprite, -1, -1, None);
+        matrix.blit(&sprite, 1, 1, Some(0));
+        let mut expected = Matrix::new(3, 3, 1);
+        expected.set_colour(0, 0, 3);
+        expected.set_colour(1, 2, 3);
+        expected.set_colour(2, 1, 3);
+        expected.set_colour(2, 2, 3);
+        crate::assert_matrix_eq!(matrix, expected);
+        matrix.blit(&sprite, 10, -5, None);
+        crate::assert_matrix_eq!(matrix, expected);
+    }
+
+    // Диагональная стенка цвета 2 делит матрицу 4x4 на две части
+    fn walled() -> Matrix {
+        let mut matrix = Matrix::new(4, 4, 1);
+        for i in 0..4 {
+            matrix.set_colour(i, 3 - i, 2);
+        }
+        matrix
+    }
+
+    #[test]
+    fn test_flood_fill_four() {
+        let mut matrix = walled();
+        matrix.flood_fill(0, 0, 3, Connectivity::Four);
+        let mut expected = walled();
+        for (x, y) in [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (2, 0)] {
+            expected.set_colour(x, y, 3);
+        }
+        crate::assert_matrix_eq!(matrix, expected);
+    }
+
+    #[test]
+    fn test_flood_fill_eight() {
+        let mut matrix = walled();
+        matrix.flood_fill(0, 3, 3, Connectivity::Eight);
+        let mut expected = Matrix::new(4, 4, 1);
+        for i in 0..4 {
+            expected.set_colour(i, 3 - i, 3);
+        }
+        crate::assert_matrix_eq!(matrix, expected);
+
+        let mut matrix = walled();
+        matrix.flood_fill(0, 3, 3, Connectivity::Four);
+        let mut expected = walled();
+        expected.set_colour(0, 3, 3);
+        crate::assert_matrix_eq!(matrix, expected);
+    }
+
+    #[test]
+    fn test_flood_fill_large() {
+        let mut matrix = Matrix::new(1000, 1000, 1);
+        matrix.flood_fill(500, 500, 2, Connectivity::Four);
+        crate::assert_matrix_eq!(matrix, Matrix::new(1000, 1000, 2));
     }
 }
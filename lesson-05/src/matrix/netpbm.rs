@@ -0,0 +1,430 @@
+// Экспорт и импорт Matrix в форматах Netpbm. Запись поддерживает PPM (P3 и P6),
+// чтение - все шесть вариантов P1-P6. Пиксели идут строками сверху вниз,
+// номера цветов переводятся в RGB и обратно через палитру.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::{Matrix, MAX_PIXELS};
+use crate::palette::{Palette, Rgb};
+
+/// Вариант PPM при записи
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetpbmFormat {
+    /// P3, числа текстом
+    Plain,
+    /// P6, по байту на канал
+    Binary,
+}
+
+#[derive(Debug)]
+pub enum NetpbmError {
+    Io(io::Error),
+    UnsupportedFormat(String),
+    InvalidHeader(String),
+    /// Данных пикселей меньше, чем заявлено в заголовке
+    Truncated,
+    InvalidSample(String),
+    /// В изображении есть цвет, которого нет в палитре
+    ColourNotInPalette {
+        x: usize,
+        y: usize,
+        rgb: Rgb,
+    },
+    /// В матрице есть номер цвета, которого нет в палитре
+    IndexNotInPalette {
+        x: usize,
+        y: usize,
+        index: u8,
+    },
+}
+
+impl fmt::Display for NetpbmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetpbmError::Io(err) => write!(f, "i/o error: {err}"),
+            NetpbmError::UnsupportedFormat(magic) => write!(f, "unsupported format '{magic}'"),
+            NetpbmError::InvalidHeader(reason) => write!(f, "invalid header: {reason}"),
+            NetpbmError::Truncated => write!(f, "pixel data is truncated"),
+            NetpbmError::InvalidSample(sample) => write!(f, "invalid sample '{sample}'"),
+            NetpbmError::ColourNotInPalette { x, y, rgb } => {
+                write!(
+                    f,
+                    "pixel ({x},{y}) has colour {rgb} which is not in the palette"
+                )
+            }
+            NetpbmError::IndexNotInPalette { x, y, index } => {
+                write!(
+                    f,
+                    "pixel ({x},{y}) has colour {index} which is not in the palette"
+                )
+            }
+        }
+    }
+}
+
+impl Error for NetpbmError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NetpbmError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NetpbmError {
+    fn from(err: io::Error) -> Self {
+        NetpbmError::Io(err)
+    }
+}
+
+impl Matrix {
+    /// Матрицу, которую read_ppm не примет (пустую или слишком большую),
+    /// не записывает: ошибка Io с видом InvalidInput
+    pub fn write_ppm<W: Write>(
+        &self,
+        mut out: W,
+        palette: &Palette,
+        format: NetpbmFormat,
+    ) -> Result<(), NetpbmError> {
+        let (width, height) = (self.width(), self.height());
+        check_size(width, height)
+            .map_err(|reason| io::Error::new(io::ErrorKind::InvalidInput, reason))?;
+        let magic = match format {
+            NetpbmFormat::Plain => "P3",
+            NetpbmFormat::Binary => "P6",
+        };
+        write!(out, "{magic}\n{width} {height}\n255\n")?;
+
+        // В P3 строки не должны быть длиннее 70 символов
+        let mut line = String::new();
+        for (y, row) in self.rows().enumerate() {
+            for (x, &index) in row.iter().enumerate() {
+                let Rgb(r, g, b) =
+                    palette
+                        .rgb(index)
+                        .ok_or(NetpbmError::IndexNotInPalette { x, y, index })?;
+                match format {
+                    NetpbmFormat::Binary => out.write_all(&[r, g, b])?,
+                    NetpbmFormat::Plain => {
+                        let pixel = format!("{r} {g} {b}");
+                        if !line.is_empty() && line.len() + 1 + pixel.len() > 70 {
+                            writeln!(out, "{line}")?;
+                            line.clear();
+                        }
+                        if !line.is_empty() {
+                            line.push(' ');
+                        }
+                        line.push_str(&pixel);
+                    }
+                }
+            }
+        }
+        if !line.is_empty() {
+            writeln!(out, "{line}")?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Читает любое изображение Netpbm. Каждый цвет должен быть в палитре.
+    /// В PGM серый уровень v означает RGB (v, v, v), в PBM 1 - черный, 0 - белый
+    pub fn read_ppm<R: Read>(mut input: R, palette: &Palette) -> Result<Matrix, NetpbmError> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        let mut reader = Reader {
+            data: &data,
+            pos: 0,
+        };
+
+        let magic = reader.token()?;
+        let kind = match magic.as_str() {
+            "P1" | "P4" => Kind::Bitmap,
+            "P2" | "P5" => Kind::Grey,
+            "P3" | "P6" => Kind::Colour,
+            _ => return Err(NetpbmError::UnsupportedFormat(magic)),
+        };
+        let binary = matches!(magic.as_str(), "P4" | "P5" | "P6");
+        let width = reader.header_number("width")?;
+        let height = reader.header_number("height")?;
+        let maxval = match kind {
+            Kind::Bitmap => 1,
+            _ => reader.header_number("maxval")?,
+        };
+        // Размеры проверяются до чтения пикселей: при нулевой стороне цикл
+        // по другой стороне крутился бы впустую, сколько бы в ней ни было
+        check_size(width, height).map_err(NetpbmError::InvalidHeader)?;
+        if maxval == 0 || maxval > u16::MAX as usize {
+            return Err(NetpbmError::InvalidHeader(format!(
+                "maxval {maxval} out of range"
+            )));
+        }
+        if binary {
+            // После заголовка ровно один пробельный символ
+            reader.pos += 1;
+        }
+
+        // Память не резервируется заранее: размеры в заголовке ещё не подтверждены данными
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let rgb = match kind {
+                    Kind::Bitmap => {
+                        let bit = if binary {
+                            let byte = reader.byte_at(y * width.div_ceil(8) + x / 8)?;
+                            (byte >> (7 - x % 8)) & 1
+                        } else {
+                            reader.bit()?
+                        };
+                        if bit == 1 {
+                            Rgb(0, 0, 0)
+                        } else {
+                            Rgb(255, 255, 255)
+                        }
+                    }
+                    Kind::Grey => {
+                        let v = reader.sample(binary, maxval)?;
+                        Rgb(v, v, v)
+                    }
+                    Kind::Colour => Rgb(
+                        reader.sample(binary, maxval)?,
+                        reader.sample(binary, maxval)?,
+                        reader.sample(binary, maxval)?,
+                    ),
+                };
+                let index = palette
+                    .index_of(rgb)
+                    .ok_or(NetpbmError::ColourNotInPalette { x, y, rgb })?;
+                pixels.push(index);
+            }
+        }
+        Ok(Matrix::from_pixels(width, height, pixels))
+    }
+}
+
+// Размеры, которые read_ppm принимает, а write_ppm записывает
+fn check_size(width: usize, height: usize) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("empty image {width}x{height}"));
+    }
+    if width as u128 * height as u128 > MAX_PIXELS as u128 {
+        return Err(format!(
+            "image {width}x{height} has more than {MAX_PIXELS} pixels"
+        ));
+    }
+    Ok(())
+}
+
+enum Kind {
+    Bitmap,
+    Grey,
+    Colour,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    // Пропускает пробелы и комментарии от # до конца строки
+    fn skip_whitespace(&mut self) {
+        while let Some(&byte) = self.data.get(self.pos) {
+            if byte == b'#' {
+                while self.data.get(self.pos).is_some_and(|&b| b != b'\n') {
+                    self.pos += 1;
+                }
+            } else if byte.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<String, NetpbmError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self
+            .data
+            .get(self.pos)
+            .is_some_and(|b| !b.is_ascii_whitespace() && *b != b'#')
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(NetpbmError::Truncated);
+        }
+        Ok(String::from_utf8_lossy(&self.data[start..self.pos]).into_owned())
+    }
+
+    fn header_number(&mut self, name: &str) -> Result<usize, NetpbmError> {
+        let token = self
+            .token()
+            .map_err(|_| NetpbmError::InvalidHeader(format!("missing {name}")))?;
+        token
+            .parse()
+            .map_err(|_| NetpbmError::InvalidHeader(format!("invalid {name} '{token}'")))
+    }
+
+    fn byte_at(&self, offset: usize) -> Result<u8, NetpbmError> {
+        self.data
+            .get(self.pos + offset)
+            .copied()
+            .ok_or(NetpbmError::Truncated)
+    }
+
+    // В P1 цифры могут идти без разделителей
+    fn bit(&mut self) -> Result<u8, NetpbmError> {
+        self.skip_whitespace();
+        let byte = self.byte_at(0)?;
+        self.pos += 1;
+        match byte {
+            b'0' => Ok(0),
+            b'1' => Ok(1),
+            _ => Err(NetpbmError::InvalidSample((byte as char).to_string())),
+        }
+    }
+
+    // Значение канала, приведённое к диапазону 0..=255
+    fn sample(&mut self, binary: bool, maxval: usize) -> Result<u8, NetpbmError> {
+        let value = if !binary {
+            let token = self.token()?;
+            token
+                .parse::<usize>()
+                .ok()
+                .filter(|&v| v <= maxval)
+                .ok_or(NetpbmError::InvalidSample(token))?
+        } else if maxval < 256 {
+            let value = self.byte_at(0)? as usize;
+            self.pos += 1;
+            value
+        } else {
+            let value = u16::from_be_bytes([self.byte_at(0)?, self.byte_at(1)?]) as usize;
+            self.pos += 2;
+            value
+        };
+        if value > maxval {
+            return Err(NetpbmError::InvalidSample(value.to_string()));
+        }
+        Ok(((value * 255 + maxval / 2) / maxval) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_matrix() -> Matrix {
+        let mut matrix = Matrix::new(3, 3, 1);
+        matrix.set_colour(0, 1, 2);
+        matrix.set_colour(2, 2, 3);
+        matrix
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let palette = Palette::default();
+        for format in [NetpbmFormat::Plain, NetpbmFormat::Binary] {
+            let mut buffer = Vec::new();
+            sample_matrix()
+                .write_ppm(&mut buffer, &palette, format)
+                .unwrap();
+            let matrix = Matrix::read_ppm(buffer.as_slice(), &palette).unwrap();
+            crate::assert_matrix_eq!(matrix, sample_matrix());
+        }
+    }
+
+    #[test]
+    fn test_plain_output() {
+        let mut buffer = Vec::new();
+        Matrix::new(2, 1, 3)
+            .write_ppm(&mut buffer, &Palette::default(), NetpbmFormat::Plain)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "P3\n2 1\n255\n0 0 255 0 0 255\n"
+        );
+    }
+
+    #[test]
+    fn test_read_grey_and_bitmap() {
+        let mut palette = Palette::new();
+        palette.insert(1, "black", Rgb(0, 0, 0));
+        palette.insert(2, "white", Rgb(255, 255, 255));
+        let mut expected = Matrix::new(2, 2, 2);
+        expected.set_colour(0, 0, 1);
+        expected.set_colour(1, 1, 1);
+
+        let pbm = b"P1\n# comment\n2 2\n10\n01\n";
+        crate::assert_matrix_eq!(Matrix::read_ppm(&pbm[..], &palette).unwrap(), expected);
+        let pbm = b"P4 2 2\n\x80\x40";
+        crate::assert_matrix_eq!(Matrix::read_ppm(&pbm[..], &palette).unwrap(), expected);
+        let pgm = b"P2 2 2 15 0 15 15 0";
+        crate::assert_matrix_eq!(Matrix::read_ppm(&pgm[..], &palette).unwrap(), expected);
+        let pgm = b"P5 2 2 255\n\x00\xff\xff\x00";
+        crate::assert_matrix_eq!(Matrix::read_ppm(&pgm[..], &palette).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_rejects_unknown_colours() {
+        let ppm = b"P3 1 2 255 255 0 0 255 128 0";
+        let err = Matrix::read_ppm(&ppm[..], &Palette::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "pixel (0,1) has colour #ff8000 which is not in the palette"
+        );
+
+        let err = Matrix::new(1, 1, 7)
+            .write_ppm(Vec::new(), &Palette::default(), NetpbmFormat::Binary)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            NetpbmError::IndexNotInPalette {
+                x: 0,
+                y: 0,
+                index: 7
+            }
+        ));
+    }
+
+    #[test]
+    fn test_rejects_broken_files() {
+        let palette = Palette::default();
+        let err = Matrix::read_ppm(&b"P7 1 1"[..], &palette).unwrap_err();
+        assert!(matches!(err, NetpbmError::UnsupportedFormat(_)));
+        let err = Matrix::read_ppm(&b"P6 2 x 255"[..], &palette).unwrap_err();
+        assert!(matches!(err, NetpbmError::InvalidHeader(_)));
+        let err = Matrix::read_ppm(&b"P6 2 1 255\n\xff\x00\x00\xff"[..], &palette).unwrap_err();
+        assert!(matches!(err, NetpbmError::Truncated));
+        let err = Matrix::read_ppm(&b"P3 1 1 255 256 0 0"[..], &palette).unwrap_err();
+        assert!(matches!(err, NetpbmError::InvalidSample(_)));
+
+        // Размеры отвергаются сразу, без цикла по строкам
+        let err = Matrix::read_ppm(&b"P3 0 99999999999 255"[..], &palette).unwrap_err();
+        assert_eq!(err.to_string(), "invalid header: empty image 0x99999999999");
+        let err = Matrix::read_ppm(&b"P6 100000 100000 255\n"[..], &palette).unwrap_err();
+        assert!(matches!(err, NetpbmError::InvalidHeader(_)));
+    }
+
+    #[test]
+    fn test_writer_rejects_what_reader_rejects() {
+        let palette = Palette::default();
+        for matrix in [Matrix::new(0, 3, 1), Matrix::new(3, 0, 1)] {
+            let mut buffer = Vec::new();
+            let err = matrix
+                .write_ppm(&mut buffer, &palette, NetpbmFormat::Plain)
+                .unwrap_err();
+            let NetpbmError::Io(err) = err else {
+                panic!("expected an i/o error, got {err:?}");
+            };
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(buffer.is_empty());
+        }
+        let err = Matrix::new(3, 0, 1)
+            .write_ppm(Vec::new(), &palette, NetpbmFormat::Binary)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "i/o error: empty image 3x0");
+    }
+}
@@ -0,0 +1,340 @@
+// Компактный двоичный формат снимков Matrix.
+//
+// Все числа little-endian:
+// * магия b"DMTX"
+// * версия формата, 1 байт
+// * ширина и высота, по u32
+// * номер палитры, u32 - что он значит, решает тот, кто сохраняет снимок
+// * строки сверху вниз, каждая - серии (длина в LEB128, номер цвета);
+//   серия не переходит на следующую строку
+// * Adler-32 всех предыдущих байт, u32
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::{Matrix, MAX_PIXELS};
+
+const MAGIC: &[u8; 4] = b"DMTX";
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+/// Матрица вместе с номером палитры, с которой её сохранили
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub matrix: Matrix,
+    pub palette_id: u32,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// Файл кончился раньше, чем описано в заголовке
+    Truncated,
+    BadMagic([u8; 4]),
+    UnsupportedVersion(u8),
+    TooLarge {
+        width: u32,
+        height: u32,
+    },
+    /// Серия нулевой длины или вылезающая за конец строки y
+    InvalidRun {
+        y: usize,
+    },
+    ChecksumMismatch {
+        expected: u32,
+        found: u32,
+    },
+    /// После контрольной суммы остались лишние байты
+    TrailingData,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "i/o error: {err}"),
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::BadMagic(magic) => write!(f, "not a snapshot, magic {magic:?}"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {version}")
+            }
+            SnapshotError::TooLarge { width, height } => {
+                write!(f, "snapshot size {width}x{height} is too large")
+            }
+            SnapshotError::InvalidRun { y } => write!(f, "invalid run in row {y}"),
+            SnapshotError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "checksum mismatch: expected {expected:08x}, found {found:08x}"
+            ),
+            SnapshotError::TrailingData => write!(f, "unexpected data after checksum"),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl Matrix {
+    /// Матрицу, которую read_snapshot не примет, не записывает: ошибка InvalidInput
+    pub fn write_snapshot<W: Write>(&self, mut out: W, palette_id: u32) -> io::Result<()> {
+        if too_large(self.width() as u64, self.height() as u64) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "snapshot size {}x{} is too large",
+                    self.width(),
+                    self.height()
+                ),
+            ));
+        }
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.push(SNAPSHOT_VERSION);
+        data.extend_from_slice(&(self.width() as u32).to_le_bytes());
+        data.extend_from_slice(&(self.height() as u32).to_le_bytes());
+        data.extend_from_slice(&palette_id.to_le_bytes());
+        for row in self.rows() {
+            let mut start = 0;
+            while start < row.len() {
+                let colour = row[start];
+                let run = row[start..].iter().take_while(|&&c| c == colour).count();
+                write_varint(&mut data, run as u32);
+                data.push(colour);
+                start += run;
+            }
+        }
+        let checksum = adler32(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        out.write_all(&data)?;
+        out.flush()
+    }
+
+    pub fn read_snapshot<R: Read>(mut reader: R) -> Result<Snapshot, SnapshotError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let mut input = Input { data: &data, at: 0 };
+
+        let magic: [u8; 4] = input.array()?;
+        if &magic != MAGIC {
+            return Err(SnapshotError::BadMagic(magic));
+        }
+        let [version] = input.array()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let width = u32::from_le_bytes(input.array()?);
+        let height = u32::from_le_bytes(input.array()?);
+        if too_large(width as u64, height as u64) {
+            return Err(SnapshotError::TooLarge { width, height });
+        }
+        let palette_id = u32::from_le_bytes(input.array()?);
+
+        let (width, height) = (width as usize, height as usize);
+        // Память растёт по мере чтения серий: заголовку без данных верить нельзя
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            let row_end = (y + 1) * width;
+            while pixels.len() < row_end {
+                let run = input.varint(y)? as usize;
+                let [colour] = input.array()?;
+                if run == 0 || pixels.len() + run > row_end {
+                    return Err(SnapshotError::InvalidRun { y });
+                }
+                pixels.resize(pixels.len() + run, colour);
+            }
+        }
+
+        let expected = adler32(&data[..input.at]);
+        let found = u32::from_le_bytes(input.array()?);
+        if found != expected {
+            return Err(SnapshotError::ChecksumMismatch { expected, found });
+        }
+        if input.at != data.len() {
+            return Err(SnapshotError::TrailingData);
+        }
+        Ok(Snapshot {
+            matrix: Matrix::from_pixels(width, height, pixels),
+            palette_id,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, palette_id: u32) -> io::Result<()> {
+        self.write_snapshot(BufWriter::new(File::create(path)?), palette_id)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Snapshot, SnapshotError> {
+        Self::read_snapshot(BufReader::new(File::open(path)?))
+    }
+}
+
+// Пустые строки тоже занимают память, поэтому нулевая сторона считается за единицу
+fn too_large(width: u64, height: u64) -> bool {
+    width.max(1) * height.max(1) > MAX_PIXELS
+}
+
+struct Input<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl Input<'_> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        let bytes = self
+            .data
+            .get(self.at..self.at + N)
+            .ok_or(SnapshotError::Truncated)?;
+        self.at += N;
+        Ok(bytes.try_into().expect("slice has length N"))
+    }
+
+    // LEB128 не длиннее пяти байт, больше u32 не бывает
+    fn varint(&mut self, y: usize) -> Result<u32, SnapshotError> {
+        let mut value = 0u64;
+        for shift in (0..35).step_by(7) {
+            let [byte] = self.array()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return u32::try_from(value).map_err(|_| SnapshotError::InvalidRun { y });
+            }
+        }
+        Err(SnapshotError::InvalidRun { y })
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MOD;
+        b = (b + a) % MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut matrix = Matrix::new(200, 3, 1);
+        matrix.set_colour(1, 1, 3);
+        let mut out = Vec::new();
+        matrix.write_snapshot(&mut out, 7).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut matrix = Matrix::new(4, 4, 2);
+        matrix.set_colour(1, 2, 3);
+        matrix.set_colour(3, 3, 0);
+        let mut out = Vec::new();
+        matrix.write_snapshot(&mut out, 42).unwrap();
+        let snapshot = Matrix::read_snapshot(out.as_slice()).unwrap();
+        crate::assert_matrix_eq!(snapshot.matrix, matrix);
+        assert_eq!(snapshot.palette_id, 42);
+
+        let empty = Matrix::new(0, 0, 1);
+        let mut out = Vec::new();
+        empty.write_snapshot(&mut out, 0).unwrap();
+        crate::assert_matrix_eq!(Matrix::read_snapshot(out.as_slice()).unwrap().matrix, empty);
+    }
+
+    #[test]
+    fn test_runs_are_compact() {
+        // Заголовок 17 байт, строки 200, 3 и 200 пикселей, контрольная сумма 4 байта
+        let data = sample();
+        assert_eq!(data.len(), 17 + 3 + 7 + 3 + 4);
+        assert_eq!(&data[17..20], &[0xc8, 0x01, 1]);
+    }
+
+    #[test]
+    fn test_rejects_damaged_files() {
+        let data = sample();
+        for length in [0, 3, 10, 20, data.len() - 1] {
+            assert!(matches!(
+                Matrix::read_snapshot(&data[..length]),
+                Err(SnapshotError::Truncated)
+            ));
+        }
+
+        let mut bad = data.clone();
+        bad[0] = b'X';
+        assert!(matches!(
+            Matrix::read_snapshot(bad.as_slice()),
+            Err(SnapshotError::BadMagic(_))
+        ));
+
+        let mut bad = data.clone();
+        bad[4] = 9;
+        let err = Matrix::read_snapshot(bad.as_slice()).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported snapshot version 9");
+
+        // Повреждённый цвет ловит контрольная сумма
+        let mut bad = data.clone();
+        bad[19] = 2;
+        assert!(matches!(
+            Matrix::read_snapshot(bad.as_slice()),
+            Err(SnapshotError::ChecksumMismatch { .. })
+        ));
+
+        // Серия длиннее строки
+        let mut bad = data.clone();
+        bad[17] = 0xc9;
+        assert!(matches!(
+            Matrix::read_snapshot(bad.as_slice()),
+            Err(SnapshotError::InvalidRun { y: 0 })
+        ));
+
+        let mut bad = data.clone();
+        bad[5..9].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Matrix::read_snapshot(bad.as_slice()),
+            Err(SnapshotError::TooLarge { .. })
+        ));
+
+        let mut bad = data.clone();
+        bad.push(0);
+        assert!(matches!(
+            Matrix::read_snapshot(bad.as_slice()),
+            Err(SnapshotError::TrailingData)
+        ));
+
+        // Огромный заголовок без серий: ошибка без резервирования памяти под пиксели
+        let mut bad = data[..17].to_vec();
+        bad[5..13].copy_from_slice(&[0x00, 0x80, 0, 0, 0x00, 0x80, 0, 0]);
+        assert!(matches!(
+            Matrix::read_snapshot(bad.as_slice()),
+            Err(SnapshotError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn test_writer_rejects_what_reader_rejects() {
+        let tall = Matrix::new(0, u32::MAX, 1);
+        let err = tall.write_snapshot(Vec::new(), 0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "snapshot size 0x4294967295 is too large");
+    }
+}
@@ -0,0 +1,120 @@
+// Экспорт Matrix в SVG. Подряд идущие пиксели одного цвета в строке
+// становятся одним <rect>, пиксели с цветом не из палитры остаются прозрачными.
+
+use std::fmt::Write;
+
+use super::Matrix;
+use crate::palette::Palette;
+
+#[derive(Debug, Clone, Default)]
+pub struct SvgOptions {
+    /// Рисовать сетку между пикселями
+    pub grid: bool,
+    /// Обвести рамкой пиксель под курсором (x, y)
+    pub cursor: Option<(u64, u64)>,
+}
+
+impl Matrix {
+    pub fn to_svg(&self, cell_size: u32, palette: &Palette) -> String {
+        self.to_svg_with_options(cell_size, palette, &SvgOptions::default())
+    }
+
+    pub fn to_svg_with_options(
+        &self,
+        cell_size: u32,
+        palette: &Palette,
+        options: &SvgOptions,
+    ) -> String {
+        let cell = cell_size as usize;
+        let (width, height) = (self.width() * cell, self.height() * cell);
+        let mut svg = String::new();
+        // Запись в String не может завершиться ошибкой
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" shape-rendering="crispEdges">"#
+        );
+
+        for (y, row) in self.rows().enumerate() {
+            let mut start = 0;
+            while start < row.len() {
+                let colour = row[start];
+                let run = row[start..].iter().take_while(|&&c| c == colour).count();
+                if let Some(rgb) = palette.rgb(colour) {
+                    let _ = writeln!(
+                        svg,
+                        r#"<rect x="{}" y="{}" width="{}" height="{cell}" fill="{rgb}"/>"#,
+                        start * cell,
+                        y * cell,
+                        run * cell,
+                    );
+                }
+                start += run;
+            }
+        }
+
+        if options.grid {
+            let _ = writeln!(svg, r##"<g stroke="#808080" stroke-width="1">"##);
+            for x in 0..=self.width() {
+                let _ = writeln!(
+                    svg,
+                    r#"<line x1="{0}" y1="0" x2="{0}" y2="{height}"/>"#,
+                    x * cell
+                );
+            }
+            for y in 0..=self.height() {
+                let _ = writeln!(
+                    svg,
+                    r#"<line x1="0" y1="{0}" x2="{width}" y2="{0}"/>"#,
+                    y * cell
+                );
+            }
+            let _ = writeln!(svg, "</g>");
+        }
+
+        if let Some((x, y)) = options.cursor {
+            let _ = writeln!(
+                svg,
+                r##"<rect x="{}" y="{}" width="{cell}" height="{cell}" fill="none" stroke="#000000" stroke-width="2"/>"##,
+                x as usize * cell,
+                y as usize * cell,
+            );
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runs() {
+        let mut matrix = Matrix::new(3, 2, 1);
+        matrix.set_colour(1, 1, 3);
+        let svg = matrix.to_svg(10, &Palette::default());
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="30" height="20""#)
+        );
+        assert!(svg.contains(r##"<rect x="0" y="0" width="30" height="10" fill="#ff0000"/>"##));
+        assert!(svg.contains(r##"<rect x="0" y="10" width="10" height="10" fill="#ff0000"/>"##));
+        assert!(svg.contains(r##"<rect x="10" y="10" width="10" height="10" fill="#0000ff"/>"##));
+        assert!(svg.contains(r##"<rect x="20" y="10" width="10" height="10" fill="#ff0000"/>"##));
+        assert_eq!(svg.matches("<rect").count(), 4);
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn test_grid_and_cursor() {
+        let options = SvgOptions {
+            grid: true,
+            cursor: Some((1, 1)),
+        };
+        let svg = Matrix::new(2, 2, 9).to_svg_with_options(4, &Palette::default(), &options);
+        // Цвета 9 нет в палитре, остаётся только рамка курсора
+        assert_eq!(svg.matches("<rect").count(), 1);
+        assert!(svg.contains(r#"<rect x="4" y="4" width="4" height="4" fill="none""#));
+        assert_eq!(svg.matches("<line").count(), 6);
+    }
+}
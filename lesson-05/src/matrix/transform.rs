@@ -0,0 +1,125 @@
+// Геометрические преобразования матрицы: повороты, отражения, транспонирование
+// и масштабирование. Направления считаются так, как матрица выводится на экран:
+// строки сверху вниз, пиксели строки слева направо.
+
+use super::Matrix;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Transform {
+    /// Поворот на 90 градусов по часовой стрелке
+    Rotate90,
+    Rotate180,
+    /// Поворот на 270 градусов по часовой стрелке, то есть на 90 против
+    Rotate270,
+    /// Отражение слева направо
+    FlipHorizontal,
+    /// Отражение сверху вниз
+    FlipVertical,
+    /// Отражение относительно главной диагонали
+    Transpose,
+}
+
+impl Transform {
+    /// Ширина и высота после преобразования
+    pub fn size(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Transform::Rotate90 | Transform::Rotate270 | Transform::Transpose => (height, width),
+            Transform::Rotate180 | Transform::FlipHorizontal | Transform::FlipVertical => {
+                (width, height)
+            }
+        }
+    }
+
+    /// Куда попадает пиксель из столбца column строки row матрицы width x height
+    pub fn map(self, column: usize, row: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Transform::Rotate90 => (height - 1 - row, column),
+            Transform::Rotate180 => (width - 1 - column, height - 1 - row),
+            Transform::Rotate270 => (row, width - 1 - column),
+            Transform::FlipHorizontal => (width - 1 - column, row),
+            Transform::FlipVertical => (column, height - 1 - row),
+            Transform::Transpose => (row, column),
+        }
+    }
+}
+
+impl Matrix {
+    pub fn transform(&self, transform: Transform) -> Matrix {
+        let (width, height) = (self.width(), self.height());
+        let (new_width, new_height) = transform.size(width, height);
+        let mut pixels = vec![0; new_width * new_height];
+        for (column, row, colour) in self.pixels() {
+            let (column, row) = transform.map(column as usize, row as usize, width, height);
+            pixels[row * new_width + column] = colour;
+        }
+        Matrix::from_pixels(new_width, new_height, pixels)
+    }
+
+    pub fn rotate90(&self) -> Matrix {
+        self.transform(Transform::Rotate90)
+    }
+
+    pub fn rotate180(&self) -> Matrix {
+        self.transform(Transform::Rotate180)
+    }
+
+    pub fn rotate270(&self) -> Matrix {
+        self.transform(Transform::Rotate270)
+    }
+
+    pub fn flip_horizontal(&self) -> Matrix {
+        self.transform(Transform::FlipHorizontal)
+    }
+
+    pub fn flip_vertical(&self) -> Matrix {
+        self.transform(Transform::FlipVertical)
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        self.transform(Transform::Transpose)
+    }
+
+    /// Масштабирование методом ближайшего соседа. Пустая матрица остаётся пустой
+    pub fn resize(&self, new_width: usize, new_height: usize) -> Matrix {
+        let (width, height) = (self.width(), self.height());
+        if width == 0 || height == 0 {
+            return Matrix::from_pixels(0, 0, Vec::new());
+        }
+        let mut pixels = Vec::with_capacity(new_width * new_height);
+        for row in 0..new_height {
+            let source = self.row(row * height / new_height);
+            pixels.extend((0..new_width).map(|column| source[column * width / new_width]));
+        }
+        Matrix::from_pixels(new_width, new_height, pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(rows: &[&[u8]]) -> Matrix {
+        Matrix::try_from(rows.iter().map(|row| row.to_vec()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_rotations_and_flips() {
+        let source = matrix(&[&[1, 2, 3], &[4, 5, 6]]);
+        crate::assert_matrix_eq!(source.rotate90(), matrix(&[&[4, 1], &[5, 2], &[6, 3]]));
+        crate::assert_matrix_eq!(source.rotate180(), matrix(&[&[6, 5, 4], &[3, 2, 1]]));
+        crate::assert_matrix_eq!(source.rotate270(), matrix(&[&[3, 6], &[2, 5], &[1, 4]]));
+        crate::assert_matrix_eq!(source.flip_horizontal(), matrix(&[&[3, 2, 1], &[6, 5, 4]]));
+        crate::assert_matrix_eq!(source.flip_vertical(), matrix(&[&[4, 5, 6], &[1, 2, 3]]));
+        crate::assert_matrix_eq!(source.transpose(), matrix(&[&[1, 4], &[2, 5], &[3, 6]]));
+        crate::assert_matrix_eq!(source.rotate90().rotate270(), source);
+    }
+
+    #[test]
+    fn test_resize() {
+        let source = matrix(&[&[1, 2], &[3, 4]]);
+        crate::assert_matrix_eq!(source.resize(4, 2), matrix(&[&[1, 1, 2, 2], &[3, 3, 4, 4]]));
+        crate::assert_matrix_eq!(source.resize(1, 1), matrix(&[&[1]]));
+        assert_eq!(source.resize(3, 0).height(), 0);
+    }
+}
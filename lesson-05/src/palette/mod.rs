@@ -0,0 +1,365 @@
+// Палитра связывает номера цветов в Matrix с именами и настоящими RGB цветами.
+// Она определяет, какие цвета допустимы в командах, как их рисовать и как
+// называть в текстовых скриптах.
+//
+// Файл палитры - текст, по цвету на строку: номер, имя и RGB в hex.
+//
+// # 16 цветов
+// 0 black  #000000
+// 1 orange #ff8000
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Entry {
+    pub index: u8,
+    pub name: String,
+    pub rgb: Rgb,
+}
+
+/// Цвета в порядке добавления. Номер и RGB ищутся по таблицам, а не перебором:
+/// поиск идёт на каждый пиксель при рисовании и чтении изображений
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "Entries", into = "Entries")
+)]
+pub struct Palette {
+    entries: Vec<Entry>,
+    // Позиция цвета в entries по его номеру
+    by_index: [Option<u8>; 256],
+    // Номер первого добавленного цвета с таким RGB
+    by_rgb: HashMap<Rgb, u8>,
+}
+
+// Для serde палитра - только список цветов, таблицы строятся заново
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entries {
+    entries: Vec<Entry>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Entries> for Palette {
+    fn from(Entries { entries }: Entries) -> Self {
+        let mut palette = Palette::new();
+        for entry in entries {
+            palette.insert(entry.index, &entry.name, entry.rgb);
+        }
+        palette
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Palette> for Entries {
+    fn from(palette: Palette) -> Self {
+        Entries {
+            entries: palette.entries,
+        }
+    }
+}
+
+impl fmt::Debug for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Palette")
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error),
+    /// Ошибка в строке файла палитры, line с единицы
+    Syntax {
+        line: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::Io(err) => write!(f, "i/o error: {err}"),
+            PaletteError::Syntax { line, reason } => write!(f, "line {line}: {reason}"),
+        }
+    }
+}
+
+impl Error for PaletteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PaletteError::Io(err) => Some(err),
+            PaletteError::Syntax { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for PaletteError {
+    fn from(err: io::Error) -> Self {
+        PaletteError::Io(err)
+    }
+}
+
+const ANSI_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl Palette {
+    /// Пустая палитра
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            by_index: [None; 256],
+            by_rgb: HashMap::new(),
+        }
+    }
+
+    /// Стандартные 16 цветов терминала: 0-7 обычные, 8-15 яркие
+    pub fn ansi16() -> Self {
+        const NORMAL: [Rgb; 8] = [
+            Rgb(0, 0, 0),
+            Rgb(128, 0, 0),
+            Rgb(0, 128, 0),
+            Rgb(128, 128, 0),
+            Rgb(0, 0, 128),
+            Rgb(128, 0, 128),
+            Rgb(0, 128, 128),
+            Rgb(192, 192, 192),
+        ];
+        const BRIGHT: [Rgb; 8] = [
+            Rgb(128, 128, 128),
+            Rgb(255, 0, 0),
+            Rgb(0, 255, 0),
+            Rgb(255, 255, 0),
+            Rgb(0, 0, 255),
+            Rgb(255, 0, 255),
+            Rgb(0, 255, 255),
+            Rgb(255, 255, 255),
+        ];
+        let mut palette = Self::new();
+        for (i, name) in ANSI_NAMES.iter().enumerate() {
+            palette.insert(i as u8, name, NORMAL[i]);
+            palette.insert(i as u8 + 8, &format!("bright_{name}"), BRIGHT[i]);
+        }
+        palette
+    }
+
+    /// Палитра xterm: 16 цветов терминала, куб 6x6x6 и 24 оттенка серого.
+    /// Цвета после первых 16 называются colourN
+    pub fn xterm256() -> Self {
+        let mut palette = Self::ansi16();
+        let level = |i: u8| if i == 0 { 0 } else { 55 + 40 * i };
+        for i in 16..=231u8 {
+            let cube = i - 16;
+            let rgb = Rgb(level(cube / 36), level(cube / 6 % 6), level(cube % 6));
+            palette.insert(i, &format!("colour{i}"), rgb);
+        }
+        for i in 232..=255u8 {
+            let grey = 8 + 10 * (i - 232);
+            palette.insert(i, &format!("colour{i}"), Rgb(grey, grey, grey));
+        }
+        palette
+    }
+
+    /// Разбирает текстовое описание палитры
+    pub fn parse(source: &str) -> Result<Self, PaletteError> {
+        let mut palette = Self::new();
+        for (number, line) in source.lines().enumerate() {
+            let syntax = |reason: String| PaletteError::Syntax {
+                line: number + 1,
+                reason,
+            };
+            // Комментарий начинается с '#' везде, кроме места RGB
+            let mut parts = Vec::new();
+            for token in line.split_whitespace() {
+                if token.starts_with('#') && parts.len() != 2 {
+                    break;
+                }
+                parts.push(token);
+            }
+            if parts.is_empty() {
+                continue;
+            }
+            let [index, name, rgb] = parts[..] else {
+                return Err(syntax(format!(
+                    "expected 'index name #rrggbb', found '{}'",
+                    line.trim()
+                )));
+            };
+            let index: u8 = index
+                .parse()
+                .map_err(|_| syntax(format!("invalid colour index '{index}'")))?;
+            let rgb = parse_hex(rgb).ok_or_else(|| syntax(format!("invalid rgb '{rgb}'")))?;
+            if palette.contains(index) {
+                return Err(syntax(format!("colour {index} is defined twice")));
+            }
+            if palette.index_by_name(name).is_some() {
+                return Err(syntax(format!("colour name '{name}' is defined twice")));
+            }
+            if name.parse::<u64>().is_ok() {
+                return Err(syntax(format!("colour name '{name}' is a number")));
+            }
+            palette.insert(index, name, rgb);
+        }
+        Ok(palette)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PaletteError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Добавляет цвет или заменяет уже существующий с тем же номером
+    pub fn insert(&mut self, index: u8, name: &str, rgb: Rgb) {
+        let entry = Entry {
+            index,
+            name: name.to_string(),
+            rgb,
+        };
+        match self.by_index[index as usize] {
+            Some(position) => {
+                self.entries[position as usize] = entry;
+                // Старый RGB мог быть первым со своим цветом, таблица строится заново
+                self.by_rgb.clear();
+                for e in &self.entries {
+                    self.by_rgb.entry(e.rgb).or_insert(e.index);
+                }
+            }
+            None => {
+                self.by_index[index as usize] = Some(self.entries.len() as u8);
+                self.by_rgb.entry(rgb).or_insert(index);
+                self.entries.push(entry);
+            }
+        }
+    }
+
+    pub fn get(&self, index: u8) -> Option<&Entry> {
+        self.by_index[index as usize].map(|position| &self.entries[position as usize])
+    }
+
+    pub fn contains(&self, index: u8) -> bool {
+        self.get(index).is_some()
+    }
+
+    pub fn rgb(&self, index: u8) -> Option<Rgb> {
+        self.get(index).map(|e| e.rgb)
+    }
+
+    /// Номер цвета по RGB, если такой цвет есть в палитре
+    pub fn index_of(&self, rgb: Rgb) -> Option<u8> {
+        self.by_rgb.get(&rgb).copied()
+    }
+
+    /// Номер цвета по имени, без учёта регистра
+    pub fn index_by_name(&self, name: &str) -> Option<u8> {
+        self.entries
+            .iter()
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+            .map(|e| e.index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Default for Palette {
+    /// 1 - красный, 2 - зеленый, 3 - синий
+    fn default() -> Self {
+        let mut palette = Self::new();
+        palette.insert(1, "red", Rgb(255, 0, 0));
+        palette.insert(2, "green", Rgb(0, 255, 0));
+        palette.insert(3, "blue", Rgb(0, 0, 255));
+        palette
+    }
+}
+
+fn parse_hex(text: &str) -> Option<Rgb> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(Rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let palette =
+            Palette::parse("# палитра\n0 black #000000\n\n7 Orange #FF8000  # комментарий\n")
+                .unwrap();
+        assert_eq!(palette.len(), 2);
+        assert_eq!(palette.index_by_name("orange"), Some(7));
+        assert_eq!(palette.rgb(7), Some(Rgb(255, 128, 0)));
+        assert_eq!(palette.index_of(Rgb(0, 0, 0)), Some(0));
+        assert!(!palette.contains(1));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = Palette::parse("1 red #ff0000\n2 red #00ff00").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: colour name 'red' is defined twice"
+        );
+        let err = Palette::parse("1 red #ff0000\n1 blue #0000ff").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: colour 1 is defined twice");
+        let err = Palette::parse("300 red #ff0000").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: invalid colour index '300'");
+        let err = Palette::parse("1 red ff0000").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: invalid rgb 'ff0000'");
+        assert!(Palette::parse("1 red").is_err());
+        assert!(Palette::parse("1 2 #ff0000").is_err());
+    }
+
+    #[test]
+    fn test_builtin_palettes() {
+        assert_eq!(Palette::ansi16().len(), 16);
+        assert_eq!(Palette::ansi16().index_by_name("bright_red"), Some(9));
+        let xterm = Palette::xterm256();
+        assert_eq!(xterm.len(), 256);
+        assert_eq!(xterm.rgb(196), Some(Rgb(255, 0, 0)));
+        assert_eq!(xterm.rgb(255), Some(Rgb(238, 238, 238)));
+    }
+
+    #[test]
+    fn test_insert_replaces() {
+        let mut palette = Palette::parse("1 red #ff0000\n2 crimson #ff0000").unwrap();
+        assert_eq!(palette.index_of(Rgb(255, 0, 0)), Some(1));
+        palette.insert(1, "blue", Rgb(0, 0, 255));
+        assert_eq!(palette.len(), 2);
+        assert_eq!(palette.get(1).map(|e| e.name.as_str()), Some("blue"));
+        assert_eq!(palette.index_of(Rgb(255, 0, 0)), Some(2));
+        assert_eq!(palette.index_of(Rgb(0, 0, 255)), Some(1));
+        assert_eq!(palette.iter().map(|e| e.index).collect::<Vec<_>>(), [1, 2]);
+    }
+}
@@ -0,0 +1,800 @@
+// Проверка потока команд до выполнения.
+//
+// Program::compile разбирает поток, раскрывает повторы и макросы и проверяет
+// всё, что не зависит от дисплея: опкоды и число аргументов, блоки, номера
+// цветов, связность заливки, размеры и координаты, которые не влезают ни в
+// один дисплей. Остальное (границы, палитра, слои, спрайты) проверяет verify
+// сухим прогоном по копии состояния дисплея, не трогая пиксели. bind делает
+// то же и возвращает VerifiedProgram, которая держит дисплей и выполняет уже
+// проверенные операции без повторных проверок. Program::compile_for раскрывает
+// повторы и макросы с бюджетом команд конкретного дисплея.
+//
+// Программа самодостаточна: макросы, определённые в дисплее раньше, ей не видны,
+// а её собственные макросы в дисплей не попадают.
+
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::command::{self, Command};
+use crate::control::{self, Interpreter, Machine, Macro};
+use crate::cursor::EdgePolicy;
+use crate::matrix::{Connectivity, Matrix, Transform};
+use crate::store::PixelStore;
+use crate::{Display, DisplayError, Location};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyError {
+    /// Поток нельзя выполнить ни на каком дисплее
+    Stream(DisplayError),
+    /// Команда не подходит дисплею, на котором программу проверяли
+    Display(DisplayError),
+}
+
+impl VerifyError {
+    pub fn error(&self) -> &DisplayError {
+        match self {
+            VerifyError::Stream(err) | VerifyError::Display(err) => err,
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Stream(err) => write!(f, "invalid command stream: {err}"),
+            VerifyError::Display(err) => write!(f, "program does not fit the display: {err}"),
+        }
+    }
+}
+
+impl Error for VerifyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error())
+    }
+}
+
+/// Поток команд, прошедший проверку. Повторы и макросы уже раскрыты
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    commands: Vec<(Location, Command)>,
+}
+
+impl Program {
+    /// Раскрывает поток с бюджетом команд по умолчанию, control::DEFAULT_BUDGET
+    pub fn compile(stream: &[u64]) -> Result<Program, VerifyError> {
+        Self::compile_with_budget(stream, control::DEFAULT_BUDGET)
+    }
+
+    /// Раскрывает поток с бюджетом команд display, см. Display::set_instruction_budget
+    pub fn compile_for<S: PixelStore>(
+        stream: &[u64],
+        display: &Display<S>,
+    ) -> Result<Program, VerifyError> {
+        Self::compile_with_budget(stream, display.instruction_budget)
+    }
+
+    fn compile_with_budget(stream: &[u64], budget: u64) -> Result<Program, VerifyError> {
+        let mut expander = Expander {
+            budget,
+            macros: HashMap::new(),
+            commands: Vec::new(),
+        };
+        let mut interpreter = Interpreter::new(&mut expander);
+        for decoded in command::decode(stream.iter().copied()) {
+            let (at, command) = decoded.map_err(VerifyError::Stream)?;
+            interpreter.feed(at, command).map_err(VerifyError::Stream)?;
+        }
+        interpreter.finish().map_err(VerifyError::Stream)?;
+        Ok(Program {
+            commands: expander.commands,
+        })
+    }
+
+    /// Сколько простых команд выполнит программа
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Проверяет, что программа выполнится на display без ошибок
+    pub fn verify<S: PixelStore>(&self, display: &Display<S>) -> Result<(), VerifyError> {
+        self.operations(display).map(|_| ())
+    }
+
+    /// Проверяет программу на display и связывает их: пока VerifiedProgram жива,
+    /// дисплей меняется только через неё, поэтому проверка не устаревает
+    pub fn bind<'d, S: PixelStore>(
+        &self,
+        display: &'d mut Display<S>,
+    ) -> Result<VerifiedProgram<'d, S>, VerifyError> {
+        let ops = self.operations(display)?;
+        Ok(VerifiedProgram { display, ops })
+    }
+
+    /// Проверяет программу и, если она подходит, выполняет её целиком.
+    /// При ошибке дисплей не меняется
+    pub fn run<S: PixelStore>(&self, display: &mut Display<S>) -> Result<(), VerifyError> {
+        self.bind(display)?.run();
+        Ok(())
+    }
+
+    fn operations<S: PixelStore>(&self, display: &Display<S>) -> Result<Vec<Op>, VerifyError> {
+        let mut shadow = Shadow::new(display);
+        self.commands
+            .iter()
+            .map(|(at, command)| {
+                let op = check(&shadow, command, *at).map_err(VerifyError::Display)?;
+                shadow.advance(op);
+                Ok(op)
+            })
+            .collect()
+    }
+}
+
+/// Программа, проверенная на конкретном дисплее, см. Program::bind
+pub struct VerifiedProgram<'d, S: PixelStore = Matrix> {
+    display: &'d mut Display<S>,
+    ops: Vec<Op>,
+}
+
+impl<S: PixelStore> VerifiedProgram<'_, S> {
+    /// Сколько простых команд выполнит программа
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Выполняет программу. Команды уже проверены, ошибок быть не может
+    pub fn run(self) {
+        for op in self.ops {
+            self.display.perform(op);
+        }
+    }
+}
+
+// Собирает простые команды вместо выполнения и проверяет то, что от дисплея не зависит
+struct Expander {
+    budget: u64,
+    macros: HashMap<u64, Macro>,
+    commands: Vec<(Location, Command)>,
+}
+
+impl Machine for Expander {
+    fn macros(&mut self) -> &mut HashMap<u64, Macro> {
+        &mut self.macros
+    }
+
+    fn instruction_budget(&self) -> u64 {
+        self.budget
+    }
+
+    fn execute(&mut self, at: Location, command: Command) -> Result<(), DisplayError> {
+        check_arguments(&command, at)?;
+        self.commands.push((at, command));
+        Ok(())
+    }
+}
+
+fn check_arguments(command: &Command, at: Location) -> Result<(), DisplayError> {
+    let colour = |colour: u64| match u8::try_from(colour) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(DisplayError::InvalidColour { at, colour }),
+    };
+    // Дисплей не бывает больше u32::MAX по каждой стороне. MOVE сюда не относится:
+    // при EdgePolicy::Clamp и Wrap он принимает любые координаты
+    let point = |x: u64, y: u64| {
+        for (name, value) in [("x", x), ("y", y)] {
+            if value >= u32::MAX as u64 {
+                return Err(DisplayError::InvalidArgument { at, name, value });
+            }
+        }
+        Ok(())
+    };
+    match *command {
+        Command::Paint { colour: c }
+        | Command::PaintNext { colour: c }
+        | Command::Circle { colour: c, .. } => colour(c),
+        Command::LineTo { x, y, colour: c }
+        | Command::Rect { x, y, colour: c }
+        | Command::FillRect { x, y, colour: c } => {
+            point(x, y)?;
+            colour(c)
+        }
+        Command::Fill {
+            connectivity,
+            colour: c,
+        } => {
+            connectivity_of(connectivity, at)?;
+            colour(c)
+        }
+        Command::StampMasked { transparent, .. } => transparent_of(transparent, at).map(|_| ()),
+        Command::Resize { width, height } => size_of(width, height, at).map(|_| ()),
+        _ => Ok(()),
+    }
+}
+
+/// Проверенная команда: все аргументы уже приведены к нужным типам,
+/// а координаты - к положению курсора с учётом политики краёв
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Op {
+    MoveTo {
+        to: (u64, u64),
+    },
+    SelectLayer {
+        layer: usize,
+    },
+    Copy {
+        width: u64,
+        height: u64,
+    },
+    Stamp {
+        sprite: usize,
+        transparent: Option<u8>,
+    },
+    Transform {
+        transform: Transform,
+    },
+    Resize {
+        width: u32,
+        height: u32,
+    },
+    Paint {
+        colour: u8,
+    },
+    PaintNext {
+        colour: u8,
+        next: (u64, u64),
+    },
+    LineTo {
+        to: (u64, u64),
+        colour: u8,
+    },
+    Rect {
+        to: (u64, u64),
+        colour: u8,
+    },
+    FillRect {
+        to: (u64, u64),
+        colour: u8,
+    },
+    Circle {
+        radius: u64,
+        colour: u8,
+    },
+    Fill {
+        connectivity: Connectivity,
+        colour: u8,
+    },
+}
+
+/// То, от чего зависит проверка команды: сам дисплей или его тень в verify
+pub(crate) trait State {
+    fn position(&self) -> (u64, u64);
+    fn boundaries(&self) -> (u32, u32);
+    fn edge_policy(&self) -> EdgePolicy;
+    fn layer_count(&self) -> usize;
+    /// Наибольшая площадь слоёв после RESIZE и наибольшее число пикселей
+    /// для FILLRECT и FILL
+    fn max_area(&self) -> u64;
+    /// Не больше ли max_area пикселей в области, которую зальёт FILL из курсора
+    fn fill_fits(&self, connectivity: Connectivity) -> bool;
+    fn check_colour(&self, colour: u64, at: Location) -> Result<u8, DisplayError>;
+    /// Номер спрайта, если он есть и его видимые цвета можно рисовать на активном слое
+    fn check_sprite(
+        &self,
+        sprite: u64,
+        transparent: Option<u8>,
+        at: Location,
+    ) -> Result<usize, DisplayError>;
+
+    // Положение курсора после сдвига на (dx, dy) с учётом политики краёв
+    fn step(&self, dx: i64, dy: i64, at: Location) -> Result<(u64, u64), DisplayError> {
+        let (x, y) = self.position();
+        self.edge_policy()
+            .resolve(
+                x as i128 + dx as i128,
+                y as i128 + dy as i128,
+                self.boundaries(),
+            )
+            .ok_or(DisplayError::StepOutOfBounds {
+                at,
+                from: (x, y),
+                by: (dx, dy),
+                boundaries: self.boundaries(),
+            })
+    }
+
+    fn check_point(&self, x: u64, y: u64, at: Location) -> Result<(u64, u64), DisplayError> {
+        let (width, height) = self.boundaries();
+        if x >= width as u64 || y >= height as u64 {
+            return Err(DisplayError::OutOfBounds {
+                at,
+                x,
+                y,
+                boundaries: (width, height),
+            });
+        }
+        Ok((x, y))
+    }
+}
+
+/// Проверяет простую команду так же, как её проверил бы дисплей в этом состоянии
+pub(crate) fn check(
+    state: &impl State,
+    command: &Command,
+    at: Location,
+) -> Result<Op, DisplayError> {
+    let op = match *command {
+        Command::MoveTo { x, y } => {
+            let to = state
+                .edge_policy()
+                .resolve(x as i128, y as i128, state.boundaries())
+                .ok_or(DisplayError::OutOfBounds {
+                    at,
+                    x,
+                    y,
+                    boundaries: state.boundaries(),
+                })?;
+            Op::MoveTo { to }
+        }
+        Command::SelectLayer { layer } => {
+            let index = usize::try_from(layer)
+                .ok()
+                .filter(|&index| index < state.layer_count())
+                .ok_or(DisplayError::InvalidArgument {
+                    at,
+                    name: "layer",
+                    value: layer,
+                })?;
+            Op::SelectLayer { layer: index }
+        }
+        Command::Copy { width, height } => Op::Copy { width, height },
+        Command::Stamp { sprite } => Op::Stamp {
+            sprite: state.check_sprite(sprite, None, at)?,
+            transparent: None,
+        },
+        Command::StampMasked {
+            sprite,
+            transparent,
+        } => {
+            let transparent = transparent_of(transparent, at)?;
+            Op::Stamp {
+                sprite: state.check_sprite(sprite, Some(transparent), at)?,
+                transparent: Some(transparent),
+            }
+        }
+        Command::Transform { transform } => Op::Transform { transform },
+        Command::Resize { width, height } => {
+            let (width, height) = size_of(width, height, at)?;
+            let area = width as u64 * height as u64;
+            if area > state.max_area() {
+                return Err(DisplayError::InvalidArgument {
+                    at,
+                    name: "area",
+                    value: area,
+                });
+            }
+            Op::Resize { width, height }
+        }
+        // Управляющие команды раскрывает control::Interpreter, сюда они не доходят
+        Command::Repeat { .. }
+        | Command::End
+        | Command::Define { .. }
+        | Command::Call { .. }
+        | Command::Params { .. } => {
+            unreachable!("control command {command:?} reached the display")
+        }
+        Command::MoveBy { dx, dy } => Op::MoveTo {
+            to: state.step(dx, dy, at)?,
+        },
+        Command::PaintNext { colour } => {
+            let colour = state.check_colour(colour, at)?;
+            // Куда сдвинется курсор, проверяем до рисования, чтобы команда не применилась наполовину
+            let next = state.step(1, 0, at)?;
+            Op::PaintNext { colour, next }
+        }
+        Command::Paint { colour } => Op::Paint {
+            colour: state.check_colour(colour, at)?,
+        },
+        Command::LineTo { x, y, colour } => {
+            let to = state.check_point(x, y, at)?;
+            let colour = state.check_colour(colour, at)?;
+            Op::LineTo { to, colour }
+        }
+        Command::Rect { x, y, colour } => {
+            let to = state.check_point(x, y, at)?;
+            let colour = state.check_colour(colour, at)?;
+            Op::Rect { to, colour }
+        }
+        Command::FillRect { x, y, colour } => {
+            let to = state.check_point(x, y, at)?;
+            let colour = state.check_colour(colour, at)?;
+            let (from_x, from_y) = state.position();
+            let area = (from_x.abs_diff(to.0) + 1).saturating_mul(from_y.abs_diff(to.1) + 1);
+            if area > state.max_area() {
+                let limit = state.max_area();
+                return Err(DisplayError::TooManyPixels { at, limit });
+            }
+            Op::FillRect { to, colour }
+        }
+        Command::Circle { radius, colour } => Op::Circle {
+            radius,
+            colour: state.check_colour(colour, at)?,
+        },
+        Command::Fill {
+            connectivity,
+            colour,
+        } => {
+            let connectivity = connectivity_of(connectivity, at)?;
+            // На пустом дисплее под курсором нет пикселя, заливать нечего
+            let (x, y) = state.position();
+            state.check_point(x, y, at)?;
+            if !state.fill_fits(connectivity) {
+                let limit = state.max_area();
+                return Err(DisplayError::TooManyPixels { at, limit });
+            }
+            Op::Fill {
+                connectivity,
+                colour: state.check_colour(colour, at)?,
+            }
+        }
+    };
+    Ok(op)
+}
+
+fn connectivity_of(value: u64, at: Location) -> Result<Connectivity, DisplayError> {
+    match value {
+        4 => Ok(Connectivity::Four),
+        8 => Ok(Connectivity::Eight),
+        value => Err(DisplayError::InvalidArgument {
+            at,
+            name: "connectivity",
+            value,
+        }),
+    }
+}
+
+fn transparent_of(value: u64, at: Location) -> Result<u8, DisplayError> {
+    u8::try_from(value).map_err(|_| DisplayError::InvalidArgument {
+        at,
+        name: "transparent",
+        value,
+    })
+}
+
+fn size_of(width: u64, height: u64, at: Location) -> Result<(u32, u32), DisplayError> {
+    let size = |value: u64, name| match u32::try_from(value) {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(DisplayError::InvalidArgument { at, name, value }),
+    };
+    Ok((size(width, "width")?, size(height, "height")?))
+}
+
+// Цвета, которые могут встретиться в слое или спрайте
+type Colours = [bool; 256];
+
+// Состояние дисплея во время сухого прогона. Пиксели не отслеживаются, поэтому
+// про спрайты, скопированные программой, известно только, какие цвета в них
+// могут быть: всё, что было в слое, плюс то, что в слой можно нарисовать.
+// Из-за этого verify строже дисплея и может отвергнуть STAMP спрайта, в котором
+// запрещённого цвета на самом деле не оказалось, или FILL на огромном дисплее,
+// где область на самом деле мала. Цвета слоёв и спрайтов дисплея
+// собираются обходом пикселей, поэтому только когда понадобятся: при COPY и STAMP
+struct Shadow<'a, S> {
+    display: &'a Display<S>,
+    cursor: (u64, u64),
+    boundaries: (u32, u32),
+    active_layer: usize,
+    layer_colours: Vec<OnceCell<Colours>>,
+    sprite_colours: Vec<OnceCell<Colours>>,
+}
+
+impl<'a, S: PixelStore> Shadow<'a, S> {
+    fn new(display: &'a Display<S>) -> Self {
+        Shadow {
+            display,
+            cursor: display.current_pixel,
+            boundaries: display.boundaries,
+            active_layer: display.active_layer,
+            layer_colours: vec![OnceCell::new(); display.layers.len()],
+            sprite_colours: vec![OnceCell::new(); display.sprites.len()],
+        }
+    }
+
+    // Цвета, которые могут оказаться в слое index: уже нарисованные и те,
+    // которыми на нём можно рисовать
+    fn layer_colours(&self, index: usize) -> Colours {
+        *self.layer_colours[index].get_or_init(|| {
+            let mut colours = self.display.layers[index].matrix.colours();
+            for (colour, possible) in colours.iter_mut().enumerate() {
+                *possible = *possible || self.display.check_colour_in(index, colour as u8);
+            }
+            colours
+        })
+    }
+
+    fn sprite_colours(&self, index: usize) -> Option<&Colours> {
+        let cell = self.sprite_colours.get(index)?;
+        Some(cell.get_or_init(|| self.display.sprites[index].colours()))
+    }
+
+    // Повторяет то, как операция меняет курсор, размеры, слой и спрайты
+    fn advance(&mut self, op: Op) {
+        match op {
+            Op::MoveTo { to } | Op::LineTo { to, .. } => self.cursor = to,
+            Op::PaintNext { next, .. } => self.cursor = next,
+            Op::SelectLayer { layer } => self.active_layer = layer,
+            Op::Copy { .. } => {
+                let colours = self.layer_colours(self.active_layer);
+                self.sprite_colours.push(OnceCell::from(colours));
+            }
+            Op::Transform { transform } => {
+                (self.cursor, self.boundaries) = crate::transform_cursor(
+                    self.cursor,
+                    self.boundaries,
+                    self.display.origin,
+                    transform,
+                );
+            }
+            Op::Resize { width, height } => {
+                self.cursor = crate::scale_cursor(
+                    self.cursor,
+                    self.boundaries,
+                    (width, height),
+                    self.display.origin,
+                );
+                self.boundaries = (width, height);
+            }
+            Op::Stamp { .. }
+            | Op::Paint { .. }
+            | Op::Rect { .. }
+            | Op::FillRect { .. }
+            | Op::Circle { .. }
+            | Op::Fill { .. } => {}
+        }
+    }
+}
+
+impl<S: PixelStore> State for Shadow<'_, S> {
+    fn position(&self) -> (u64, u64) {
+        self.cursor
+    }
+
+    fn boundaries(&self) -> (u32, u32) {
+        self.boundaries
+    }
+
+    fn edge_policy(&self) -> EdgePolicy {
+        self.display.edge_policy
+    }
+
+    fn layer_count(&self) -> usize {
+        self.display.layers.len()
+    }
+
+    fn max_area(&self) -> u64 {
+        S::MAX_AREA
+    }
+
+    // Пиксели в тени не отслеживаются, поэтому в расчёт идёт весь дисплей
+    fn fill_fits(&self, _connectivity: Connectivity) -> bool {
+        let (width, height) = self.boundaries;
+        width as u64 * height as u64 <= S::MAX_AREA
+    }
+
+    fn check_colour(&self, colour: u64, at: Location) -> Result<u8, DisplayError> {
+        match u8::try_from(colour) {
+            Ok(index) if self.display.check_colour_in(self.active_layer, index) => Ok(index),
+            _ => Err(DisplayError::InvalidColour { at, colour }),
+        }
+    }
+
+    fn check_sprite(
+        &self,
+        sprite: u64,
+        transparent: Option<u8>,
+        at: Location,
+    ) -> Result<usize, DisplayError> {
+        let (index, colours) = usize::try_from(sprite)
+            .ok()
+            .and_then(|index| Some((index, self.sprite_colours(index)?)))
+            .ok_or(DisplayError::InvalidArgument {
+                at,
+                name: "sprite",
+                value: sprite,
+            })?;
+        for (colour, &possible) in colours.iter().enumerate() {
+            if possible && Some(colour as u8) != transparent {
+                self.check_colour(colour as u64, at)?;
+            }
+        }
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::Matrix;
+    use crate::{create_display, process_commands};
+
+    fn location(command: usize, offset: usize, opcode: u64) -> Location {
+        Location {
+            command,
+            offset,
+            opcode,
+        }
+    }
+
+    #[test]
+    fn test_matches_process_commands() {
+        let stream = vec![
+            1, 1, 1, 5, 3, 3, 2, 21, 2, 9, 3, 22, 11, 2, 2, 1, 0, 0, 12, 0,
+        ];
+        let program = Program::compile(&stream).unwrap();
+        assert_eq!(program.len(), 7);
+
+        let mut expected = create_display(4, 4, 1);
+        process_commands(&mut expected, stream).unwrap();
+        let mut display = create_display(4, 4, 1);
+        program.run(&mut display).unwrap();
+        crate::assert_matrix_eq!(*display.matrix(), *expected.matrix());
+        assert_eq!(display.current_pixel, expected.current_pixel);
+        assert_eq!(display.sprites(), expected.sprites());
+
+        // Проверенная программа отменяется так же, как обычные команды
+        assert_ne!(display.matrix().colour(0, 0), 1);
+        assert!(display.undo());
+        assert_eq!(display.matrix().colour(0, 0), 1);
+    }
+
+    #[test]
+    fn test_rejects_stream() {
+        let err = Program::compile(&[1, 2]).unwrap_err();
+        assert!(matches!(
+            err,
+            VerifyError::Stream(DisplayError::MissingArguments { .. })
+        ));
+        let err = Program::compile(&[2, 3, 2, 256]).unwrap_err();
+        assert_eq!(
+            err,
+            VerifyError::Stream(DisplayError::InvalidColour {
+                at: location(1, 2, 2),
+                colour: 256
+            })
+        );
+        let err = Program::compile(&[7, 6, 1]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid command stream: command #0 at offset 0: invalid connectivity 6"
+        );
+        let err = Program::compile(&[3, 0, u32::MAX as u64, 2]).unwrap_err();
+        assert!(matches!(
+            err.error(),
+            DisplayError::InvalidArgument { name: "y", .. }
+        ));
+        // Макросы дисплея программе не видны
+        let err = Program::compile(&[24, 1, 0]).unwrap_err();
+        assert!(matches!(
+            err.error(),
+            DisplayError::InvalidArgument { name: "macro", .. }
+        ));
+        assert!(Program::compile(&[21, 2, 2, 1]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_before_touching_display() {
+        // Последняя команда уводит курсор за край, первые две не должны примениться
+        let program = Program::compile(&[2, 3, 21, 4, 9, 2, 22]).unwrap();
+        let mut display = create_display(3, 3, 1);
+        let err = program.run(&mut display).unwrap_err();
+        assert_eq!(
+            err,
+            VerifyError::Display(DisplayError::StepOutOfBounds {
+                at: location(2, 4, 9),
+                from: (2, 0),
+                by: (1, 0),
+                boundaries: (3, 3),
+            })
+        );
+        crate::assert_matrix_eq!(*display.matrix(), Matrix::new(3, 3, 1));
+        assert_eq!(display.current_pixel, (0, 0));
+        assert!(!display.undo());
+
+        // На дисплее побольше та же программа выполняется
+        let mut display = create_display(5, 5, 1);
+        program.run(&mut display).unwrap();
+        assert_eq!(display.current_pixel, (4, 0));
+
+        // Границы проверяются с учётом поворотов и масштабирования
+        let program = Program::compile(&[14, 20, 2, 8, 1, 1, 1]).unwrap();
+        assert!(program.verify(&create_display(3, 3, 1)).is_ok());
+        let program = Program::compile(&[20, 2, 8, 1, 2, 1]).unwrap();
+        let err = program.verify(&create_display(3, 3, 1)).unwrap_err();
+        assert!(matches!(
+            err.error(),
+            DisplayError::OutOfBounds { x: 2, y: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn test_bind_and_budget() {
+        let program = Program::compile(&[21, 2, 9, 3, 22]).unwrap();
+        let mut display = create_display(3, 2, 1);
+        let verified = program.bind(&mut display).unwrap();
+        assert_eq!(verified.len(), 2);
+        verified.run();
+        assert_eq!(display.current_pixel, (2, 0));
+        assert_eq!(display.matrix().colour(1, 0), 3);
+        // Курсор уже у края, второй раз программа не подходит
+        assert!(program.bind(&mut display).is_err());
+
+        // Бюджет дисплея действует и при раскрытии программы
+        let stream = [21, 10, 2, 1, 22];
+        display.set_instruction_budget(5);
+        assert!(Program::compile(&stream).is_ok());
+        let err = Program::compile_for(&stream, &display).unwrap_err();
+        assert!(matches!(
+            err,
+            VerifyError::Stream(DisplayError::BudgetExceeded { budget: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn test_move_follows_edge_policy() {
+        // MOVE за край отвергает дисплей, а не сам поток
+        let stream = vec![1, u64::MAX, 5, 2, 3];
+        let program = Program::compile(&stream).unwrap();
+        let mut display = create_display(3, 3, 1);
+        assert!(matches!(
+            program.verify(&display).unwrap_err(),
+            VerifyError::Display(DisplayError::OutOfBounds { .. })
+        ));
+
+        display.set_edge_policy(EdgePolicy::Clamp);
+        let mut expected = create_display(3, 3, 1);
+        expected.set_edge_policy(EdgePolicy::Clamp);
+        process_commands(&mut expected, stream).unwrap();
+        program.run(&mut display).unwrap();
+        assert_eq!(display.current_pixel, (2, 2));
+        crate::assert_matrix_eq!(*display.matrix(), *expected.matrix());
+    }
+
+    #[test]
+    fn test_colours_and_sprites() {
+        let mut display = create_display(3, 3, 1);
+        let overlay = display.add_layer(0);
+        // Прозрачным цветом можно рисовать только на слое, где он прозрачный
+        let program = Program::compile(&[10, 1, 2, 0]).unwrap();
+        assert!(program.verify(&display).is_ok());
+        let program = Program::compile(&[2, 0]).unwrap();
+        assert!(matches!(
+            program.verify(&display).unwrap_err().error(),
+            DisplayError::InvalidColour { colour: 0, .. }
+        ));
+
+        // Спрайт со слоя может содержать прозрачный цвет: на нижний слой его
+        // можно нарисовать только с маской
+        assert_eq!(overlay, 1);
+        let program = Program::compile(&[10, 1, 11, 2, 2, 10, 0, 12, 0]).unwrap();
+        assert!(matches!(
+            program.verify(&display).unwrap_err().error(),
+            DisplayError::InvalidColour { colour: 0, .. }
+        ));
+        let program = Program::compile(&[10, 1, 11, 2, 2, 10, 0, 13, 0, 0]).unwrap();
+        assert!(program.verify(&display).is_ok());
+        let program = Program::compile(&[12, 0]).unwrap();
+        assert!(matches!(
+            program.verify(&display).unwrap_err().error(),
+            DisplayError::InvalidArgument { name: "sprite", .. }
+        ));
+    }
+}
@@ -0,0 +1,279 @@
+// Способы вывода матрицы в текстовый поток. Каждый Renderer получает матрицу
+// и палитру и пишет результат в любой io::Write.
+
+use std::io::{self, Write};
+
+use crate::matrix::Matrix;
+use crate::palette::{Palette, Rgb};
+
+pub trait Renderer {
+    fn render(&self, matrix: &Matrix, palette: &Palette, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Цветные кружки эмодзи, по символу на пиксель
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmojiRenderer;
+
+/// Пиксель - два пробела с 24-битным цветом фона
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnsiRenderer;
+
+/// Пиксель - символ ASCII: номерам 0-61 соответствуют 0-9, A-Z и a-z, цвета не
+/// из палитры рисуются '?'. Все номера от 62 и выше по умолчанию выглядят как '#'
+/// и на выводе не различаются, для них символ задаётся через with_glyph.
+/// Вывод не зависит от терминала, поэтому подходит для логов CI и сравнения в тестах
+#[derive(Debug, Clone, Default)]
+pub struct AsciiRenderer {
+    glyphs: Vec<(u8, char)>,
+}
+
+/// Символ ▀ с цветом текста сверху и цветом фона снизу,
+/// так в одной строке терминала помещаются две строки матрицы
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HalfBlockRenderer;
+
+/// Матрица относительно более раннего состояния before: пиксель - два символа,
+/// у изменённых пикселей перед символом цвета стоит '*'
+#[derive(Debug, Clone)]
+pub struct DiffRenderer {
+    before: Matrix,
+    glyphs: AsciiRenderer,
+}
+
+// Кружки эмодзи и их примерные цвета
+const EMOJI: [(char, Rgb); 9] = [
+    ('\u{1F534}', Rgb(221, 46, 68)),   // Красный кружок
+    ('\u{1F7E0}', Rgb(244, 144, 12)),  // Оранжевый кружок
+    ('\u{1F7E1}', Rgb(253, 203, 88)),  // Жёлтый кружок
+    ('\u{1F7E2}', Rgb(120, 177, 89)),  // Зелёный кружок
+    ('\u{1F535}', Rgb(85, 172, 238)),  // Синий кружок
+    ('\u{1F7E3}', Rgb(170, 142, 214)), // Фиолетовый кружок
+    ('\u{1F7E4}', Rgb(193, 105, 79)),  // Коричневый кружок
+    ('\u{26AB}', Rgb(49, 55, 61)),     // Чёрный кружок
+    ('\u{26AA}', Rgb(230, 231, 232)),  // Белый кружок
+];
+
+// Символы для номеров цветов по умолчанию: 0-9, A-Z, a-z
+const GLYPHS: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+// Ближайший по цвету кружок, пробел для цветов не из палитры
+fn color_to_char(color: Option<Rgb>) -> char {
+    let Some(Rgb(r, g, b)) = color else {
+        return ' ';
+    };
+    let distance = |&(_, Rgb(er, eg, eb)): &(char, Rgb)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, er) + d(g, eg) + d(b, eb)
+    };
+    EMOJI
+        .iter()
+        .min_by_key(|e| distance(e))
+        .map_or(' ', |e| e.0)
+}
+
+impl Renderer for EmojiRenderer {
+    fn render(&self, matrix: &Matrix, palette: &Palette, out: &mut dyn Write) -> io::Result<()> {
+        for row in matrix.rows() {
+            let line: String = row
+                .iter()
+                .map(|&cell| color_to_char(palette.rgb(cell)))
+                .collect();
+            writeln!(out, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Renderer for AnsiRenderer {
+    fn render(&self, matrix: &Matrix, palette: &Palette, out: &mut dyn Write) -> io::Result<()> {
+        for row in matrix.rows() {
+            for &cell in row {
+                match palette.rgb(cell) {
+                    Some(Rgb(r, g, b)) => write!(out, "\x1b[48;2;{r};{g};{b}m  ")?,
+                    None => write!(out, "\x1b[49m  ")?,
+                }
+            }
+            writeln!(out, "\x1b[0m")?;
+        }
+        Ok(())
+    }
+}
+
+impl AsciiRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Задаёт свой символ для номера цвета
+    pub fn with_glyph(mut self, index: u8, glyph: char) -> Self {
+        self.glyphs.retain(|&(i, _)| i != index);
+        self.glyphs.push((index, glyph));
+        self
+    }
+
+    fn glyph(&self, index: u8, palette: &Palette) -> char {
+        if let Some(&(_, glyph)) = self.glyphs.iter().find(|&&(i, _)| i == index) {
+            return glyph;
+        }
+        if !palette.contains(index) {
+            return '?';
+        }
+        GLYPHS.get(index as usize).map_or('#', |&b| b as char)
+    }
+}
+
+impl Renderer for AsciiRenderer {
+    fn render(&self, matrix: &Matrix, palette: &Palette, out: &mut dyn Write) -> io::Result<()> {
+        for row in matrix.rows() {
+            let line: String = row.iter().map(|&cell| self.glyph(cell, palette)).collect();
+            writeln!(out, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl DiffRenderer {
+    pub fn new(before: Matrix) -> Self {
+        Self {
+            before,
+            glyphs: AsciiRenderer::new(),
+        }
+    }
+
+    /// Символы цветов берутся из glyphs
+    pub fn with_glyphs(mut self, glyphs: AsciiRenderer) -> Self {
+        self.glyphs = glyphs;
+        self
+    }
+}
+
+impl Renderer for DiffRenderer {
+    fn render(&self, matrix: &Matrix, palette: &Palette, out: &mut dyn Write) -> io::Result<()> {
+        let mut before = self.before.rows();
+        for row in matrix.rows() {
+            // Пиксели за пределами before тоже считаются изменёнными
+            let before_row = before.next().unwrap_or_default();
+            let mut line = String::new();
+            for (x, &cell) in row.iter().enumerate() {
+                line.push(if before_row.get(x) == Some(&cell) {
+                    ' '
+                } else {
+                    '*'
+                });
+                line.push(self.glyphs.glyph(cell, palette));
+            }
+            writeln!(out, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Renderer for HalfBlockRenderer {
+    fn render(&self, matrix: &Matrix, palette: &Palette, out: &mut dyn Write) -> io::Result<()> {
+        let rows: Vec<&[u8]> = matrix.rows().collect();
+        for pair in rows.chunks(2) {
+            for (x, &top) in pair[0].iter().enumerate() {
+                match palette.rgb(top) {
+                    Some(Rgb(r, g, b)) => write!(out, "\x1b[38;2;{r};{g};{b}m")?,
+                    None => write!(out, "\x1b[39m")?,
+                }
+                // У последней нечётной строки нижней половины нет
+                match pair.get(1).and_then(|bottom| palette.rgb(bottom[x])) {
+                    Some(Rgb(r, g, b)) => write!(out, "\x1b[48;2;{r};{g};{b}m")?,
+                    None => write!(out, "\x1b[49m")?,
+                }
+                write!(out, "\u{2580}")?;
+            }
+            writeln!(out, "\x1b[0m")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(renderer: &dyn Renderer, matrix: &Matrix) -> String {
+        let mut out = Vec::new();
+        matrix
+            .render(renderer, &Palette::default(), &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn sample_matrix() -> Matrix {
+        let mut matrix = Matrix::new(3, 3, 1);
+        matrix.set_colour(1, 1, 3);
+        matrix.set_colour(2, 2, 2);
+        matrix
+    }
+
+    #[test]
+    fn test_emoji() {
+        assert_eq!(
+            render(&EmojiRenderer, &Matrix::new(2, 1, 2)),
+            "\u{1F7E2}\u{1F7E2}\n"
+        );
+        let palette = Palette::default();
+        assert_eq!(color_to_char(palette.rgb(1)), '\u{1F534}');
+        assert_eq!(color_to_char(palette.rgb(3)), '\u{1F535}');
+        assert_eq!(color_to_char(palette.rgb(4)), ' ');
+        let orange = Palette::parse("4 orange #ff8000").unwrap();
+        assert_eq!(color_to_char(orange.rgb(4)), '\u{1F7E0}');
+    }
+
+    #[test]
+    fn test_ascii() {
+        assert_eq!(
+            render(&AsciiRenderer::new(), &sample_matrix()),
+            "111\n131\n112\n"
+        );
+        let renderer = AsciiRenderer::new().with_glyph(1, '.');
+        assert_eq!(render(&renderer, &sample_matrix()), "...\n.3.\n..2\n");
+        assert_eq!(render(&renderer, &Matrix::new(1, 1, 9)), "?\n");
+
+        // Номера от 62 совпадают, пока им не задан свой символ
+        let mut matrix = Matrix::new(3, 1, 61);
+        matrix.set_colour(1, 0, 62);
+        matrix.set_colour(2, 0, 200);
+        let xterm = Palette::xterm256();
+        let mut out = Vec::new();
+        matrix
+            .render(&AsciiRenderer::new(), &xterm, &mut out)
+            .unwrap();
+        assert_eq!(out, b"z##\n");
+        let renderer = AsciiRenderer::new().with_glyph(200, '@');
+        let mut out = Vec::new();
+        matrix.render(&renderer, &xterm, &mut out).unwrap();
+        assert_eq!(out, b"z#@\n");
+    }
+
+    #[test]
+    fn test_diff() {
+        let renderer = DiffRenderer::new(Matrix::new(3, 3, 1));
+        assert_eq!(
+            render(&renderer, &sample_matrix()),
+            " 1 1 1\n 1*3 1\n 1 1*2\n"
+        );
+        let renderer = DiffRenderer::new(Matrix::new(1, 1, 1));
+        assert_eq!(render(&renderer, &Matrix::new(2, 2, 1)), " 1*1\n*1*1\n");
+    }
+
+    #[test]
+    fn test_ansi() {
+        assert_eq!(
+            render(&AnsiRenderer, &Matrix::new(1, 2, 3)),
+            "\x1b[48;2;0;0;255m  \x1b[0m\n\x1b[48;2;0;0;255m  \x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn test_half_block() {
+        let output = render(&HalfBlockRenderer, &sample_matrix());
+        assert_eq!(output.lines().count(), 2);
+        assert_eq!(output.matches('\u{2580}').count(), 6);
+        let last = output.lines().last().unwrap();
+        assert!(last.starts_with("\x1b[38;2;255;0;0m\x1b[49m\u{2580}"));
+    }
+}
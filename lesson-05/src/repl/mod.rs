@@ -0,0 +1,337 @@
+// Интерактивный режим: дисплей живёт между строками ввода, каждая строка
+// команд применяется сразу, после чего дисплей перерисовывается.
+//
+// Строка команд - числовой поток (`1 2 2 2 3`) или команда скрипта (`PAINT blue`).
+// Строка применяется целиком или не применяется вовсе. Строки открытого блока
+// (`repeat 3 {`, `macro ... {`) копятся до закрывающей `}` и применяются вместе,
+// пока блок открыт, приглашение - `... `. Мета-команды:
+// :show, :reset, :undo, :redo, :save файл, :load файл, :help, :quit
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use crate::matrix::{Matrix, NetpbmFormat};
+use crate::palette::Palette;
+use crate::render::{EmojiRenderer, Renderer};
+use crate::script::ParseError;
+use crate::{
+    create_display_from_matrix, create_display_with_palette, execute_commands, process_commands,
+    script, Display, DisplayError,
+};
+
+const HELP: &str = "\
+Команды: числа (1 x y, 2 colour, ...) или скрипт (MOVE x y, PAINT colour, ...)
+:show        - показать дисплей
+:reset       - вернуть дисплей к исходному состоянию
+:undo, :redo - отменить или повторить команду
+:save файл   - сохранить дисплей в PPM
+:load файл   - загрузить дисплей из PPM
+:quit        - выйти";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineError {
+    Parse(ParseError),
+    Display(DisplayError),
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineError::Parse(err) => err.fmt(f),
+            LineError::Display(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for LineError {}
+
+/// Применяет строку числовых команд или команду скрипта к дисплею.
+/// Строки, начинающиеся с цифры, считаются числовым потоком
+pub fn apply_line(display: &mut Display, line: &str) -> Result<(), LineError> {
+    if line.trim_start().starts_with(|c: char| c.is_ascii_digit()) {
+        let commands = script::parse_numbers(line).map_err(LineError::Parse)?;
+        process_commands(display, commands).map_err(LineError::Display)
+    } else {
+        let commands =
+            script::parse_with_palette(line, display.palette()).map_err(LineError::Parse)?;
+        execute_commands(display, &commands).map_err(LineError::Display)
+    }
+}
+
+/// Что делать после строки ввода
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Quit,
+}
+
+pub struct Repl {
+    width: u32,
+    height: u32,
+    default_colour: u8,
+    display: Display,
+    renderer: Box<dyn Renderer>,
+    prompt: bool,
+    // Строки ещё не закрытого блока и его вложенность
+    pending: String,
+    depth: isize,
+}
+
+impl Repl {
+    pub fn new(width: u32, height: u32, default_colour: u8, palette: Palette) -> Self {
+        Self {
+            width,
+            height,
+            default_colour,
+            display: create_display_with_palette(width, height, default_colour, palette),
+            renderer: Box::new(EmojiRenderer),
+            prompt: true,
+            pending: String::new(),
+            depth: 0,
+        }
+    }
+
+    pub fn with_renderer(mut self, renderer: Box<dyn Renderer>) -> Self {
+        self.renderer = renderer;
+        self
+    }
+
+    /// Печатать ли приглашение `> ` перед каждой строкой
+    pub fn with_prompt(mut self, prompt: bool) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// Читает строки до :quit или конца ввода
+    pub fn run(&mut self, input: impl BufRead, out: &mut impl Write) -> io::Result<()> {
+        self.print_prompt(out)?;
+        for line in input.lines() {
+            if self.execute_line(&line?, out)? == Flow::Quit {
+                return Ok(());
+            }
+            self.print_prompt(out)?;
+        }
+        if self.prompt {
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    fn print_prompt(&self, out: &mut impl Write) -> io::Result<()> {
+        if self.prompt {
+            let prompt = if self.pending.is_empty() {
+                "> "
+            } else {
+                "... "
+            };
+            write!(out, "{prompt}")?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Выполняет одну строку. Ошибки команд пишутся в out и не прерывают работу
+    pub fn execute_line(&mut self, line: &str, out: &mut impl Write) -> io::Result<Flow> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(Flow::Continue);
+        }
+        if !self.pending.is_empty() || script::block_balance(line) > 0 {
+            self.pending.push_str(line);
+            self.pending.push('\n');
+            self.depth += script::block_balance(line);
+            if self.depth <= 0 {
+                let block = std::mem::take(&mut self.pending);
+                self.depth = 0;
+                match self.apply(&block) {
+                    Ok(()) => self.show(out)?,
+                    Err(message) => writeln!(out, "Ошибка: {message}")?,
+                }
+            }
+            return Ok(Flow::Continue);
+        }
+        let Some(meta) = line.strip_prefix(':') else {
+            match self.apply(line) {
+                Ok(()) => self.show(out)?,
+                Err(message) => writeln!(out, "Ошибка: {message}")?,
+            }
+            return Ok(Flow::Continue);
+        };
+
+        let (name, argument) = match meta.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (meta, ""),
+        };
+        match (name, argument) {
+            ("quit" | "q", "") => return Ok(Flow::Quit),
+            ("show", "") => self.show(out)?,
+            ("help", "") => writeln!(out, "{HELP}")?,
+            ("reset", "") => {
+                let palette = self.display.palette().clone();
+                self.display = create_display_with_palette(
+                    self.width,
+                    self.height,
+                    self.default_colour,
+                    palette,
+                );
+                self.show(out)?;
+            }
+            ("undo", "") => {
+                if self.display.undo() {
+                    self.show(out)?;
+                } else {
+                    writeln!(out, "Нечего отменять")?;
+                }
+            }
+            ("redo", "") => {
+                if self.display.redo() {
+                    self.show(out)?;
+                } else {
+                    writeln!(out, "Нечего повторять")?;
+                }
+            }
+            ("save", path) if !path.is_empty() => match self.save(path) {
+                Ok(()) => writeln!(out, "Сохранено в {path}")?,
+                Err(message) => writeln!(out, "Ошибка: {message}")?,
+            },
+            ("load", path) if !path.is_empty() => match self.load(path) {
+                Ok(()) => self.show(out)?,
+                Err(message) => writeln!(out, "Ошибка: {message}")?,
+            },
+            _ => writeln!(out, "Неизвестная команда :{meta}, список команд - :help")?,
+        }
+        Ok(Flow::Continue)
+    }
+
+    fn show(&self, out: &mut impl Write) -> io::Result<()> {
+        self.display
+            .flatten()
+            .render(self.renderer.as_ref(), self.display.palette(), out)
+    }
+
+    // Применяет строку целиком: при ошибке откатывает уже выполненные команды
+    fn apply(&mut self, line: &str) -> Result<(), String> {
+        let checkpoint = self.display.checkpoint();
+        apply_line(&mut self.display, line).map_err(|err| {
+            if matches!(err, LineError::Display(_)) && !self.display.undo_to_checkpoint(checkpoint)
+            {
+                return format!("{err} (часть команд уже применена)");
+            }
+            err.to_string()
+        })
+    }
+
+    fn save(&self, path: &str) -> Result<(), String> {
+        // Изображение собирается в памяти, чтобы при ошибке не оставить пустой файл
+        let mut image = Vec::new();
+        self.display
+            .flatten()
+            .write_ppm(&mut image, self.display.palette(), NetpbmFormat::Plain)
+            .map_err(|err| format!("{path}: {err}"))?;
+        std::fs::write(path, image).map_err(|err| format!("{path}: {err}"))
+    }
+
+    fn load(&mut self, path: &str) -> Result<(), String> {
+        let file = File::open(path).map_err(|err| format!("{path}: {err}"))?;
+        let matrix = Matrix::read_ppm(BufReader::new(file), self.display.palette())
+            .map_err(|err| format!("{path}: {err}"))?;
+        let palette = self.display.palette().clone();
+        self.display = create_display_from_matrix(matrix, palette);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::AsciiRenderer;
+
+    fn run(repl: &mut Repl, input: &str) -> String {
+        let mut out = Vec::new();
+        repl.run(input.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn ascii_repl() -> Repl {
+        Repl::new(3, 3, 1, Palette::default()).with_renderer(Box::new(AsciiRenderer::new()))
+    }
+
+    #[test]
+    fn test_incremental_commands() {
+        let mut repl = ascii_repl();
+        let out = run(&mut repl, "1 1 1 2 3\nPAINT green\n:undo\n");
+        assert_eq!(
+            out,
+            "> 111\n131\n111\n> 111\n121\n111\n> 111\n131\n111\n> \n"
+        );
+    }
+
+    #[test]
+    fn test_without_prompt() {
+        let mut repl = ascii_repl().with_prompt(false);
+        assert_eq!(run(&mut repl, "2 2\n"), "211\n111\n111\n");
+    }
+
+    #[test]
+    fn test_errors_do_not_exit() {
+        let mut repl = ascii_repl();
+        let out = run(
+            &mut repl,
+            "1 1 1 2 3 1 9 9\nJUMP\n:nope\n:show\n:quit\n2 2\n",
+        );
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            "> Ошибка: command #2 at offset 5: move to (9,9) outside 3x3"
+        );
+        assert_eq!(
+            lines[1],
+            "> Ошибка: line 1, column 1: unknown command 'JUMP'"
+        );
+        assert!(lines[2].starts_with("> Неизвестная команда :nope"));
+        // Строка с ошибкой откатилась целиком
+        assert_eq!(&lines[3..], ["> 111", "111", "111", "> "]);
+    }
+
+    #[test]
+    fn test_multiline_blocks() {
+        let mut repl = ascii_repl();
+        let out = run(&mut repl, "repeat 2 {\n  PAINTNEXT green\n}\n2 3\n");
+        assert_eq!(out, "> ... ... 221\n111\n111\n> 223\n111\n111\n> \n");
+
+        // Ошибка внутри блока откатывает весь блок
+        let out = run(&mut repl, "repeat 2 {\n  PAINT 3\n  MOVE 9 9\n}\n:show\n");
+        assert!(out.starts_with("> ... ... ... Ошибка: command #2"));
+        assert!(out.ends_with("> 223\n111\n111\n> \n"));
+
+        let out = run(
+            &mut repl,
+            "macro corner {\n  MOVE 0 2\n  PAINT 3\n}\nCALL corner\n",
+        );
+        assert!(out.ends_with("> 223\n111\n311\n> \n"));
+    }
+
+    #[test]
+    fn test_reset_save_load() {
+        let path = std::env::temp_dir().join(format!("repl-{}.ppm", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut repl = ascii_repl();
+        run(&mut repl, &format!("1 2 2 2 2\n:save {path}\n:reset\n"));
+        crate::assert_matrix_eq!(*repl.display().matrix(), Matrix::new(3, 3, 1));
+
+        run(&mut repl, &format!(":load {path}\n"));
+        let mut expected = Matrix::new(3, 3, 1);
+        expected.set_colour(2, 2, 2);
+        crate::assert_matrix_eq!(*repl.display().matrix(), expected);
+        std::fs::remove_file(path).unwrap();
+
+        let out = run(&mut repl, ":load /nonexistent/file.ppm\n");
+        assert!(out.contains("Ошибка: /nonexistent/file.ppm"));
+    }
+}
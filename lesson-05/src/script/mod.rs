@@ -0,0 +1,792 @@
+// Текстовый язык команд дисплея. Каждая строка содержит одну команду:
+//
+// # рисуем синий пиксель
+// MOVE 2 2
+// PAINT blue
+//
+// Мнемоники и имена цветов не зависят от регистра, всё после `#` считается
+// комментарием. Цвета можно писать номером или именем из палитры.
+// Скрипт компилируется в те же Command, что и числовой поток process_commands.
+//
+// Повторы и макросы записываются блоками в фигурных скобках, скобки отделяются
+// пробелами, а блок можно уместить и в одну строку:
+//
+// macro dot x y colour {
+//   MOVE $x $y
+//   PAINT $colour
+// }
+// repeat 4 { PAINTNEXT red }
+// dot 1 2 blue
+// CALL dot 2 3 green
+//
+// Макрос из этого же скрипта можно вызывать просто по имени, макрос из
+// предыдущих скриптов (например, строк REPL) - через CALL. Аргументом может быть
+// и отрицательное число, оно пригодится для MOVEBY: `step -1`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::command::{self, Command, MacroArgs, MAX_MACRO_PARAMS};
+use crate::cursor::zigzag_decode;
+use crate::matrix::Transform;
+use crate::palette::Palette;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Номер строки, с единицы
+    pub line: usize,
+    /// Номер символа в строке, с единицы
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnknownMnemonic(String),
+    InvalidNumber(String),
+    UnknownColour(String),
+    MissingArgument {
+        mnemonic: &'static str,
+        expected: usize,
+    },
+    UnexpectedToken(String),
+    /// `}` без открытого блока
+    UnmatchedBrace,
+    /// Блок, открытый в этой позиции, не закрыт до конца скрипта
+    UnclosedBlock,
+    InvalidMacroName(String),
+    UnknownParameter(String),
+    WrongArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::UnknownMnemonic(token) => write!(f, "unknown command '{token}'"),
+            ParseErrorKind::InvalidNumber(token) => write!(f, "expected a number, found '{token}'"),
+            ParseErrorKind::UnknownColour(token) => write!(f, "unknown colour '{token}'"),
+            ParseErrorKind::MissingArgument { mnemonic, expected } => {
+                write!(f, "{mnemonic} expects {expected} arguments")
+            }
+            ParseErrorKind::UnexpectedToken(token) => write!(f, "unexpected '{token}'"),
+            ParseErrorKind::UnmatchedBrace => write!(f, "'}}' without an open block"),
+            ParseErrorKind::UnclosedBlock => write!(f, "block is never closed"),
+            ParseErrorKind::InvalidMacroName(name) => write!(f, "invalid macro name '{name}'"),
+            ParseErrorKind::UnknownParameter(token) => write!(f, "unknown parameter '{token}'"),
+            ParseErrorKind::WrongArgumentCount {
+                name,
+                expected,
+                found,
+            } => write!(f, "{name} expects {expected} arguments, found {found}"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Разбирает скрипт в список команд, имена цветов берутся из стандартной палитры
+pub fn parse(source: &str) -> Result<Vec<Command>, ParseError> {
+    parse_with_palette(source, &Palette::default())
+}
+
+pub fn parse_with_palette(source: &str, palette: &Palette) -> Result<Vec<Command>, ParseError> {
+    let commands = parse_with_lines(source, palette)?;
+    Ok(commands.into_iter().map(|(_, command)| command).collect())
+}
+
+/// Как parse_with_palette, но вместе с каждой командой возвращает номер её строки
+/// (с единицы), чтобы ошибку дисплея можно было показать на нужной строке
+pub fn parse_with_lines(
+    source: &str,
+    palette: &Palette,
+) -> Result<Vec<(usize, Command)>, ParseError> {
+    let mut commands = Vec::new();
+    let mut blocks: Vec<Block> = Vec::new();
+    // Макросы этого скрипта и число их параметров
+    let mut macros = HashMap::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or_default();
+        let mut tokens = Tokens {
+            line: index + 1,
+            tokens: tokenize(code),
+            position: 0,
+            end: code.chars().count() + 1,
+            palette,
+            params: scope(&blocks),
+            slot: 0,
+            mask: 0,
+        };
+        while let Some((column, word)) = tokens.next() {
+            if word == "}" {
+                if blocks.pop().is_none() {
+                    return Err(ParseError {
+                        line: tokens.line,
+                        column,
+                        kind: ParseErrorKind::UnmatchedBrace,
+                    });
+                }
+                commands.push((tokens.line, Command::End));
+                tokens.params = scope(&blocks);
+                continue;
+            }
+            let mut block = Block {
+                line: tokens.line,
+                column,
+                params: None,
+            };
+            match word.to_ascii_uppercase().as_str() {
+                "REPEAT" => {
+                    let count = tokens.number("REPEAT", 2)?;
+                    tokens.open_brace("REPEAT", 2)?;
+                    let line = tokens.line;
+                    commands.extend(tokens.params_marker().map(|params| (line, params)));
+                    commands.push((line, Command::Repeat { count }));
+                }
+                "MACRO" => {
+                    let (name, params) = tokens.macro_header()?;
+                    // Имя известно уже внутри тела, чтобы макрос мог вызвать сам себя
+                    macros.insert(name.to_ascii_uppercase(), params.len());
+                    commands.push((
+                        tokens.line,
+                        Command::Define {
+                            id: macro_id(name),
+                            params: params.len() as u64,
+                        },
+                    ));
+                    block.params = Some(params);
+                }
+                _ => {
+                    let command = tokens.command(column, word, &macros)?;
+                    tokens.finish()?;
+                    let line = tokens.line;
+                    commands.extend(tokens.params_marker().map(|params| (line, params)));
+                    commands.push((line, command));
+                    continue;
+                }
+            }
+            blocks.push(block);
+            tokens.params = scope(&blocks);
+        }
+    }
+    match blocks.last() {
+        Some(block) => Err(ParseError {
+            line: block.line,
+            column: block.column,
+            kind: ParseErrorKind::UnclosedBlock,
+        }),
+        None => Ok(commands),
+    }
+}
+
+/// На сколько строка меняет вложенность блоков: `{` открывает блок, `}` закрывает.
+/// По нему построчный ввод (REPL, CLI) понимает, что блок продолжается дальше
+pub fn block_balance(line: &str) -> isize {
+    let code = line.split('#').next().unwrap_or_default();
+    code.split_whitespace()
+        .map(|token| match token {
+            "{" => 1,
+            "}" => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Номер макроса name в числовом потоке. Зависит только от имени (без учёта
+/// регистра), поэтому макрос из одного скрипта можно вызвать из другого
+pub fn macro_id(name: &str) -> u64 {
+    // FNV-1a
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in name.bytes() {
+        hash ^= byte.to_ascii_uppercase() as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Открытый блок скрипта
+struct Block {
+    line: usize,
+    column: usize,
+    // Имена параметров, если блок - определение макроса
+    params: Option<Vec<String>>,
+}
+
+// Параметры, на которые можно ссылаться внутри самого глубокого макроса
+fn scope(blocks: &[Block]) -> Vec<String> {
+    blocks
+        .iter()
+        .rev()
+        .find_map(|block| block.params.clone())
+        .unwrap_or_default()
+}
+
+// Имя макроса или параметра: буквы, цифры и `_`, начинается не с цифры
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Разбирает числа через пробелы и переводы строк, например `1 2 2 2 3`
+pub fn parse_numbers(source: &str) -> Result<Vec<u64>, ParseError> {
+    let mut numbers = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        for (column, token) in tokenize(line) {
+            numbers.push(token.parse().map_err(|_| ParseError {
+                line: index + 1,
+                column,
+                kind: ParseErrorKind::InvalidNumber(token.to_string()),
+            })?);
+        }
+    }
+    Ok(numbers)
+}
+
+/// Разбирает скрипт сразу в числовой поток для process_commands
+pub fn compile(source: &str) -> Result<Vec<u64>, ParseError> {
+    parse(source).map(|commands| command::encode(&commands))
+}
+
+// Слова строки вместе с номером колонки, в которой они начинаются
+fn tokenize(code: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (column, (byte, ch)) in code.char_indices().enumerate() {
+        match (ch.is_whitespace(), start) {
+            (false, None) => start = Some((column + 1, byte)),
+            (true, Some((token_column, token_byte))) => {
+                tokens.push((token_column, &code[token_byte..byte]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((column, byte)) = start {
+        tokens.push((column, &code[byte..]));
+    }
+    tokens
+}
+
+struct Tokens<'a> {
+    line: usize,
+    tokens: Vec<(usize, &'a str)>,
+    position: usize,
+    // Колонка сразу за последним символом строки
+    end: usize,
+    palette: &'a Palette,
+    // Параметры макроса, внутри которого стоит команда
+    params: Vec<String>,
+    // Номер следующего аргумента команды и аргументы-ссылки на параметры
+    slot: usize,
+    mask: u64,
+}
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Option<(usize, &'a str)> {
+        let token = self.tokens.get(self.position).copied();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|&(_, token)| token)
+    }
+
+    // PARAMS для только что разобранной команды, если она ссылается на параметры
+    fn params_marker(&mut self) -> Option<Command> {
+        self.slot = 0;
+        let mask = std::mem::take(&mut self.mask);
+        (mask != 0).then_some(Command::Params { mask })
+    }
+
+    // Команда, которая начинается со слова mnemonic в колонке column
+    fn command(
+        &mut self,
+        column: usize,
+        mnemonic: &'a str,
+        macros: &HashMap<String, usize>,
+    ) -> Result<Command, ParseError> {
+        let command = match mnemonic.to_ascii_uppercase().as_str() {
+            "MOVE" => {
+                let x = self.number("MOVE", 2)?;
+                let y = self.number("MOVE", 2)?;
+                Command::MoveTo { x, y }
+            }
+            "PAINT" => {
+                let colour = self.colour("PAINT", 1)?;
+                Command::Paint { colour }
+            }
+            "LINE" => {
+                let x = self.number("LINE", 3)?;
+                let y = self.number("LINE", 3)?;
+                let colour = self.colour("LINE", 3)?;
+                Command::LineTo { x, y, colour }
+            }
+            "RECT" => {
+                let x = self.number("RECT", 3)?;
+                let y = self.number("RECT", 3)?;
+                let colour = self.colour("RECT", 3)?;
+                Command::Rect { x, y, colour }
+            }
+            "FILLRECT" => {
+                let x = self.number("FILLRECT", 3)?;
+                let y = self.number("FILLRECT", 3)?;
+                let colour = self.colour("FILLRECT", 3)?;
+                Command::FillRect { x, y, colour }
+            }
+            "CIRCLE" => {
+                let radius = self.number("CIRCLE", 2)?;
+                let colour = self.colour("CIRCLE", 2)?;
+                Command::Circle { radius, colour }
+            }
+            "MOVEBY" => {
+                let dx = self.signed("MOVEBY", 2)?;
+                let dy = self.signed("MOVEBY", 2)?;
+                Command::MoveBy { dx, dy }
+            }
+            "PAINTNEXT" => {
+                let colour = self.colour("PAINTNEXT", 1)?;
+                Command::PaintNext { colour }
+            }
+            "LAYER" => {
+                let layer = self.number("LAYER", 1)?;
+                Command::SelectLayer { layer }
+            }
+            "COPY" => {
+                let width = self.number("COPY", 2)?;
+                let height = self.number("COPY", 2)?;
+                Command::Copy { width, height }
+            }
+            "STAMP" => {
+                let sprite = self.number("STAMP", 1)?;
+                Command::Stamp { sprite }
+            }
+            "STAMPMASKED" => {
+                let sprite = self.number("STAMPMASKED", 2)?;
+                let transparent = self.colour("STAMPMASKED", 2)?;
+                Command::StampMasked {
+                    sprite,
+                    transparent,
+                }
+            }
+            "ROTATE90" => Command::Transform {
+                transform: Transform::Rotate90,
+            },
+            "ROTATE180" => Command::Transform {
+                transform: Transform::Rotate180,
+            },
+            "ROTATE270" => Command::Transform {
+                transform: Transform::Rotate270,
+            },
+            "FLIPH" => Command::Transform {
+                transform: Transform::FlipHorizontal,
+            },
+            "FLIPV" => Command::Transform {
+                transform: Transform::FlipVertical,
+            },
+            "TRANSPOSE" => Command::Transform {
+                transform: Transform::Transpose,
+            },
+            "RESIZE" => {
+                let width = self.number("RESIZE", 2)?;
+                let height = self.number("RESIZE", 2)?;
+                Command::Resize { width, height }
+            }
+            "FILL" => {
+                let connectivity = self.number("FILL", 2)?;
+                let colour = self.colour("FILL", 2)?;
+                Command::Fill {
+                    connectivity,
+                    colour,
+                }
+            }
+            "CALL" => {
+                let (column, name) = self.argument("CALL", 1)?;
+                self.call(column, name, macros)?
+            }
+            name if macros.contains_key(name) => self.call(column, mnemonic, macros)?,
+            _ => {
+                return Err(ParseError {
+                    line: self.line,
+                    column,
+                    kind: ParseErrorKind::UnknownMnemonic(mnemonic.to_string()),
+                })
+            }
+        };
+        Ok(command)
+    }
+
+    // Вызов макроса name, аргументы идут до конца строки или `}`
+    fn call(
+        &mut self,
+        column: usize,
+        name: &str,
+        macros: &HashMap<String, usize>,
+    ) -> Result<Command, ParseError> {
+        let mut args = Vec::new();
+        while self.peek().is_some_and(|token| token != "}") {
+            let (column, token) = self.next().expect("token is peeked");
+            if args.len() == MAX_MACRO_PARAMS {
+                return Err(ParseError {
+                    line: self.line,
+                    column,
+                    kind: ParseErrorKind::UnexpectedToken(token.to_string()),
+                });
+            }
+            // У CALL перед аргументами идут id и argc
+            self.slot = 2 + args.len();
+            // Отрицательное число передаётся в дополнительном коде
+            let value = match token.parse::<i64>() {
+                Ok(value) if value < 0 => value as u64,
+                _ => self.colour_value(column, token)?,
+            };
+            args.push(value);
+        }
+        // Макросы из других скриптов не известны, их аргументы проверит дисплей
+        if let Some(&expected) = macros.get(&name.to_ascii_uppercase()) {
+            if args.len() != expected {
+                return Err(ParseError {
+                    line: self.line,
+                    column,
+                    kind: ParseErrorKind::WrongArgumentCount {
+                        name: name.to_string(),
+                        expected,
+                        found: args.len(),
+                    },
+                });
+            }
+        }
+        Ok(Command::Call {
+            id: macro_id(name),
+            args: MacroArgs::new(&args).expect("argument count is checked"),
+        })
+    }
+
+    // Имя и параметры макроса до открывающей скобки
+    fn macro_header(&mut self) -> Result<(&'a str, Vec<String>), ParseError> {
+        let (column, name) = self.argument("MACRO", 2)?;
+        let is_mnemonic = (0..=command::OP_PARAMS)
+            .filter_map(command::mnemonic)
+            .any(|mnemonic| mnemonic.eq_ignore_ascii_case(name));
+        if !is_identifier(name) || is_mnemonic {
+            return Err(ParseError {
+                line: self.line,
+                column,
+                kind: ParseErrorKind::InvalidMacroName(name.to_string()),
+            });
+        }
+        let mut params: Vec<String> = Vec::new();
+        loop {
+            let (column, token) = self.argument("MACRO", 2)?;
+            if token == "{" {
+                return Ok((name, params));
+            }
+            let duplicate = params.iter().any(|param| param.eq_ignore_ascii_case(token));
+            if !is_identifier(token) || duplicate || params.len() == MAX_MACRO_PARAMS {
+                return Err(ParseError {
+                    line: self.line,
+                    column,
+                    kind: ParseErrorKind::UnexpectedToken(token.to_string()),
+                });
+            }
+            params.push(token.to_string());
+        }
+    }
+
+    fn open_brace(&mut self, mnemonic: &'static str, expected: usize) -> Result<(), ParseError> {
+        match self.argument(mnemonic, expected)? {
+            (_, "{") => Ok(()),
+            (column, token) => Err(ParseError {
+                line: self.line,
+                column,
+                kind: ParseErrorKind::UnexpectedToken(token.to_string()),
+            }),
+        }
+    }
+
+    // Ссылка на параметр макроса вида $name вместо очередного аргумента команды.
+    // Номер параметра становится аргументом, а сам аргумент отмечается в mask
+    fn parameter(&mut self, column: usize, token: &str) -> Option<Result<u64, ParseError>> {
+        let slot = self.slot;
+        self.slot += 1;
+        let name = token.strip_prefix('$')?;
+        self.mask |= 1 << slot;
+        let index = self
+            .params
+            .iter()
+            .position(|param| param.eq_ignore_ascii_case(name));
+        Some(index.map(|index| index as u64).ok_or(ParseError {
+            line: self.line,
+            column,
+            kind: ParseErrorKind::UnknownParameter(token.to_string()),
+        }))
+    }
+
+    fn argument(
+        &mut self,
+        mnemonic: &'static str,
+        expected: usize,
+    ) -> Result<(usize, &'a str), ParseError> {
+        self.next().ok_or(ParseError {
+            line: self.line,
+            column: self.end,
+            kind: ParseErrorKind::MissingArgument { mnemonic, expected },
+        })
+    }
+
+    fn number(&mut self, mnemonic: &'static str, expected: usize) -> Result<u64, ParseError> {
+        let (column, token) = self.argument(mnemonic, expected)?;
+        if let Some(value) = self.parameter(column, token) {
+            return value;
+        }
+        token.parse().map_err(|_| ParseError {
+            line: self.line,
+            column,
+            kind: ParseErrorKind::InvalidNumber(token.to_string()),
+        })
+    }
+
+    fn signed(&mut self, mnemonic: &'static str, expected: usize) -> Result<i64, ParseError> {
+        let (column, token) = self.argument(mnemonic, expected)?;
+        // Номер параметра кодируется так, чтобы после zigzag получилось само число
+        if let Some(value) = self.parameter(column, token) {
+            return value.map(zigzag_decode);
+        }
+        token.parse().map_err(|_| ParseError {
+            line: self.line,
+            column,
+            kind: ParseErrorKind::InvalidNumber(token.to_string()),
+        })
+    }
+
+    fn colour(&mut self, mnemonic: &'static str, expected: usize) -> Result<u64, ParseError> {
+        let (column, token) = self.argument(mnemonic, expected)?;
+        self.colour_value(column, token)
+    }
+
+    // Номер, имя цвета из палитры или ссылка на параметр
+    fn colour_value(&mut self, column: usize, token: &str) -> Result<u64, ParseError> {
+        if let Some(value) = self.parameter(column, token) {
+            return value;
+        }
+        token
+            .parse()
+            .ok()
+            .or_else(|| self.palette.index_by_name(token).map(u64::from))
+            .ok_or(ParseError {
+                line: self.line,
+                column,
+                kind: ParseErrorKind::UnknownColour(token.to_string()),
+            })
+    }
+
+    // После команды строка кончается или закрывается блок
+    fn finish(&mut self) -> Result<(), ParseError> {
+        if self.peek() == Some("}") {
+            return Ok(());
+        }
+        match self.next() {
+            Some((column, token)) => Err(ParseError {
+                line: self.line,
+                column,
+                kind: ParseErrorKind::UnexpectedToken(token.to_string()),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::Matrix;
+    use crate::{create_display, execute_commands, process_commands, EdgePolicy};
+
+    #[test]
+    fn test_compiles_to_numeric_stream() {
+        let source = "# синий пиксель\nMOVE 2 2\n\n  paint blue  # комментарий\n";
+        assert_eq!(compile(source).unwrap(), vec![1, 2, 2, 2, 3]);
+    }
+
+    #[test]
+    fn test_script_matches_process_commands() {
+        let commands = parse("MOVE 3 2\nPAINT red\nMove 2 3\nPAINT 2").unwrap();
+        let mut from_script = create_display(5, 5, 3);
+        execute_commands(&mut from_script, &commands).unwrap();
+
+        let mut from_stream = create_display(5, 5, 3);
+        process_commands(&mut from_stream, vec![1, 3, 2, 2, 1, 1, 2, 3, 2, 2]).unwrap();
+
+        let mut expected = Matrix::new(5, 5, 3);
+        expected.set_colour(3, 2, 1);
+        expected.set_colour(2, 3, 2);
+        crate::assert_matrix_eq!(*from_script.matrix(), expected);
+        crate::assert_matrix_eq!(*from_stream.matrix(), expected);
+    }
+
+    #[test]
+    fn test_shapes() {
+        let source =
+            "MOVE 0 0\nLINE 3 3 red\nRECT 0 0 green\nFILLRECT 1 2 blue\nCIRCLE 2 1\nFILL 8 red\nMOVEBY -1 2\nPAINTNEXT blue\nLAYER 1\nCOPY 2 3\nSTAMP 0\nSTAMPMASKED 0 red\nROTATE90\nflipv\nRESIZE 8 8";
+        assert_eq!(
+            compile(source).unwrap(),
+            vec![
+                1, 0, 0, 3, 3, 3, 1, 4, 0, 0, 2, 5, 1, 2, 3, 6, 2, 1, 7, 8, 1, 8, 1, 4, 9, 3, 10,
+                1, 11, 2, 3, 12, 0, 13, 0, 1, 14, 18, 20, 8, 8
+            ]
+        );
+    }
+
+    #[test]
+    fn test_palette_names() {
+        let palette = Palette::parse("1 red #ff0000\n7 orange #ff8000").unwrap();
+        let commands = parse_with_palette("PAINT Orange\nPAINT red", &palette).unwrap();
+        assert_eq!(command::encode(&commands), vec![2, 7, 2, 1]);
+        assert!(parse_with_palette("PAINT blue", &palette).is_err());
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        assert_eq!(parse_numbers(" 1 2  2 ").unwrap(), vec![1, 2, 2]);
+        let err = parse_numbers("1 2 x").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 1, column 5: expected a number, found 'x'"
+        );
+        assert_eq!(parse_numbers("1 2\n\n 2 3\n").unwrap(), vec![1, 2, 2, 3]);
+        let err = parse_numbers("1 2 2\n2 3\n  9 y").unwrap_err();
+        assert_eq!((err.line, err.column), (3, 5));
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let err = parse("MOVE 1 1\nPAINT orange").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError {
+                line: 2,
+                column: 7,
+                kind: ParseErrorKind::UnknownColour("orange".to_string()),
+            }
+        );
+        assert_eq!(err.to_string(), "line 2, column 7: unknown colour 'orange'");
+
+        let err = parse("  JUMP 1 1").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 3));
+        assert!(matches!(err.kind, ParseErrorKind::UnknownMnemonic(_)));
+
+        let err = parse("MOVE 1 x").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 8));
+        assert!(matches!(err.kind, ParseErrorKind::InvalidNumber(_)));
+
+        let err = parse("MOVE 1 # y забыли").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 8));
+        assert!(matches!(
+            err.kind,
+            ParseErrorKind::MissingArgument {
+                mnemonic: "MOVE",
+                expected: 2
+            }
+        ));
+
+        let err = parse("PAINT 1 2").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 9));
+        assert!(matches!(err.kind, ParseErrorKind::UnexpectedToken(_)));
+    }
+
+    #[test]
+    fn test_blocks() {
+        let source = "macro pair first second {\n  PAINTNEXT $first\n  PAINTNEXT $second\n}\nrepeat 2 {\n  repeat 2 { pair blue red }\n  repeat 2 { CALL pair red blue }\n}";
+        let commands = parse(source).unwrap();
+        let id = macro_id("PAIR");
+        let mut expected_stream = vec![23, id, 2, 25, 1, 9, 0, 25, 1, 9, 1, 22];
+        expected_stream.extend([
+            21, 2, 21, 2, 24, id, 2, 3, 1, 22, 21, 2, 24, id, 2, 1, 3, 22, 22,
+        ]);
+        assert_eq!(command::encode(&commands), expected_stream);
+
+        // Шахматная доска: курсор переносится на следующую строку
+        let mut display = create_display(4, 4, 2);
+        display.set_edge_policy(EdgePolicy::Wrap);
+        execute_commands(&mut display, &commands).unwrap();
+        let mut expected = Matrix::new(4, 4, 1);
+        for x in 0..4 {
+            for y in 0..4 {
+                if (x + y) % 2 == 0 {
+                    expected.set_colour(x, y, 3);
+                }
+            }
+        }
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+    }
+
+    #[test]
+    fn test_macro_parameters() {
+        let source = "macro step dx { MOVEBY $dx 0\nPAINT green }\nstep 2\nstep -1";
+        let mut display = create_display(4, 4, 1);
+        execute_commands(&mut display, &parse(source).unwrap()).unwrap();
+        let mut expected = Matrix::new(4, 4, 1);
+        expected.set_colour(2, 0, 2);
+        expected.set_colour(1, 0, 2);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+
+        // Параметр в заголовке REPEAT и большое смещение рядом с параметром
+        let source =
+            "macro row n { MOVEBY -5000000000000000000 0\nrepeat $n { PAINTNEXT $n } }\nrow 3";
+        let commands = parse(source).unwrap();
+        assert_eq!(
+            command::encode(&commands)[3..14],
+            [8, 9_999_999_999_999_999_999, 0, 25, 1, 21, 0, 25, 1, 9, 0]
+        );
+        display.set_edge_policy(EdgePolicy::Clamp);
+        execute_commands(&mut display, &commands).unwrap();
+        for x in 0..3 {
+            expected.set_colour(x, 0, 3);
+        }
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+    }
+
+    #[test]
+    fn test_block_errors() {
+        let err = parse("PAINT 1\n}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 1: '}' without an open block"
+        );
+
+        let err = parse("MOVE 0 0\n  repeat 3 {\nPAINT 1").unwrap_err();
+        assert_eq!(err.to_string(), "line 2, column 3: block is never closed");
+
+        let err = parse("repeat 3 PAINT 1").unwrap_err();
+        assert_eq!(err.to_string(), "line 1, column 10: unexpected 'PAINT'");
+
+        let err = parse("macro dot x { PAINT $y }").unwrap_err();
+        assert_eq!(err.to_string(), "line 1, column 21: unknown parameter '$y'");
+
+        let err = parse("PAINT $x").unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::UnknownParameter(_)));
+
+        let err = parse("macro dot x { PAINT $x }\ndot 1 2").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 1: dot expects 1 arguments, found 2"
+        );
+
+        for name in ["paint", "1dot", "a-b"] {
+            let err = parse(&format!("macro {name} {{ }}")).unwrap_err();
+            assert_eq!(err.kind, ParseErrorKind::InvalidMacroName(name.to_string()));
+        }
+
+        // Макрос из другого скрипта вызывается только через CALL
+        assert!(parse("CALL dot 1").is_ok());
+        assert!(matches!(
+            parse("dot 1").unwrap_err().kind,
+            ParseErrorKind::UnknownMnemonic(_)
+        ));
+    }
+}
@@ -0,0 +1,231 @@
+// Сериализация Display через serde (фича "serde"). Сохраняются курсор, размеры,
+// слои, палитра, политика края и спрайты. История отмены и макросы не сохраняются,
+// у восстановленного дисплея они пустые.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use std::collections::HashMap;
+
+use crate::control;
+use crate::history::{self, History};
+use crate::layer::Layer;
+use crate::matrix::{Matrix, MAX_PIXELS};
+use crate::palette::Palette;
+use crate::{Display, EdgePolicy, Origin};
+
+#[derive(Serialize)]
+struct DisplayRef<'a> {
+    cursor: (u64, u64),
+    boundaries: (u32, u32),
+    layers: &'a [Layer],
+    active_layer: usize,
+    background: u8,
+    palette: &'a Palette,
+    edge_policy: EdgePolicy,
+    origin: Origin,
+    sprites: &'a [Matrix],
+}
+
+#[derive(Deserialize)]
+struct DisplayState {
+    cursor: (u64, u64),
+    boundaries: (u32, u32),
+    layers: Vec<Layer>,
+    #[serde(default)]
+    active_layer: usize,
+    #[serde(default)]
+    background: u8,
+    #[serde(default)]
+    palette: Palette,
+    #[serde(default)]
+    edge_policy: EdgePolicy,
+    #[serde(default)]
+    origin: Origin,
+    #[serde(default)]
+    sprites: Vec<Matrix>,
+}
+
+impl Serialize for Display {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DisplayRef {
+            cursor: self.current_pixel,
+            boundaries: self.boundaries,
+            layers: &self.layers,
+            active_layer: self.active_layer,
+            background: self.background,
+            palette: &self.palette,
+            edge_policy: self.edge_policy,
+            origin: self.origin,
+            sprites: &self.sprites,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Display {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = DisplayState::deserialize(deserializer)?;
+        let (width, height) = state.boundaries;
+        if width as u64 * height as u64 > MAX_PIXELS {
+            return Err(D::Error::custom(format!(
+                "display {width}x{height} has more than {MAX_PIXELS} pixels"
+            )));
+        }
+        // На пустом дисплее курсор стоит в (0, 0), как после create_display
+        let (x, y) = state.cursor;
+        let empty = width == 0 || height == 0;
+        let valid = if empty {
+            (x, y) == (0, 0)
+        } else {
+            x < width as u64 && y < height as u64
+        };
+        if !valid {
+            return Err(D::Error::custom(format!(
+                "cursor ({x},{y}) is outside {width}x{height}"
+            )));
+        }
+        if state.layers.is_empty() {
+            return Err(D::Error::custom("display has no layers"));
+        }
+        if state.active_layer >= state.layers.len() {
+            return Err(D::Error::custom(format!(
+                "active layer {} does not exist",
+                state.active_layer
+            )));
+        }
+        let size = (width as usize, height as usize);
+        for (index, layer) in state.layers.iter().enumerate() {
+            let matrix = layer.matrix();
+            if (matrix.width(), matrix.height()) != size {
+                return Err(D::Error::custom(format!(
+                    "layer {index} is {}x{}, expected {width}x{height}",
+                    matrix.width(),
+                    matrix.height()
+                )));
+            }
+        }
+        Ok(Display {
+            current_pixel: state.cursor,
+            boundaries: state.boundaries,
+            layers: state.layers,
+            active_layer: state.active_layer,
+            background: state.background,
+            palette: state.palette,
+            edge_policy: state.edge_policy,
+            origin: state.origin,
+            history: History::new(history::DEFAULT_LIMIT),
+            pending: Vec::new(),
+            sprites: state.sprites,
+            macros: HashMap::new(),
+            instruction_budget: control::DEFAULT_BUDGET,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::{self, Command, MacroArgs};
+    use crate::matrix::Matrix;
+    use crate::{create_display, process_commands, Display};
+
+    #[test]
+    fn test_matrix_json() {
+        let mut matrix = Matrix::new(3, 2, 1);
+        matrix.set_colour(2, 1, 3);
+        let json = serde_json::to_string(&matrix).unwrap();
+        assert_eq!(json, "[[1,1,1],[1,1,3]]");
+        assert_eq!(serde_json::from_str::<Matrix>(&json).unwrap(), matrix);
+        let err = serde_json::from_str::<Matrix>("[[1,1],[1]]").unwrap_err();
+        assert_eq!(err.to_string(), "row 1 has 1 pixels, expected 2");
+    }
+
+    #[test]
+    fn test_commands_json() {
+        let commands = command::decode(vec![1, 2, 3, 8, 1, 2, 15])
+            .map(|decoded| decoded.map(|(_, command)| command))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let json = serde_json::to_string(&commands).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"MoveTo":{"x":2,"y":3}},{"MoveBy":{"dx":-1,"dy":1}},{"Transform":{"transform":"Rotate180"}}]"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Vec<Command>>(&json).unwrap(),
+            commands
+        );
+    }
+
+    #[test]
+    fn test_macro_args_json() {
+        let call = Command::Call {
+            id: 7,
+            args: MacroArgs::new(&[1, 2]).unwrap(),
+        };
+        let json = serde_json::to_string(&call).unwrap();
+        assert_eq!(json, r#"{"Call":{"id":7,"args":[1,2]}}"#);
+        assert_eq!(serde_json::from_str::<Command>(&json).unwrap(), call);
+
+        let err =
+            serde_json::from_str::<Command>(r#"{"Call":{"id":7,"args":[0,0,0,0,0,0,0,0,0]}}"#)
+                .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("macro call has 9 arguments, at most 8 allowed"));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let mut display = create_display(3, 3, 1);
+        display.add_layer(0);
+        process_commands(&mut display, vec![10, 1, 1, 2, 1, 2, 3, 11, 1, 1]).unwrap();
+        let json = serde_json::to_value(&display).unwrap();
+        assert_eq!(json["cursor"], serde_json::json!([2, 1]));
+        assert_eq!(json["boundaries"], serde_json::json!([3, 3]));
+
+        let restored: Display = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(restored.current_pixel, (2, 1));
+        assert_eq!(restored.active_layer(), 1);
+        crate::assert_matrix_eq!(restored.flatten(), display.flatten());
+        assert_eq!(restored.sprites(), display.sprites());
+        assert_eq!(serde_json::to_value(&restored).unwrap(), json);
+
+        let mut broken = json;
+        broken["cursor"] = serde_json::json!([3, 0]);
+        let Err(err) = serde_json::from_value::<Display>(broken) else {
+            panic!("cursor outside the display is accepted");
+        };
+        assert_eq!(err.to_string(), "cursor (3,0) is outside 3x3");
+    }
+
+    #[test]
+    fn test_empty_display_round_trip() {
+        let mut wide = create_display(4, 3, 1);
+        process_commands(&mut wide, vec![1, 2, 1]).unwrap();
+        wide.resize(4, 0);
+        assert_eq!(wide.current_pixel, (0, 0));
+        for display in [create_display(0, 0, 1), create_display(0, 5, 1), wide] {
+            let json = serde_json::to_value(&display).unwrap();
+            let restored: Display = serde_json::from_value(json.clone()).unwrap();
+            assert_eq!(restored.boundaries, display.boundaries);
+            assert_eq!(restored.current_pixel, (0, 0));
+            crate::assert_matrix_eq!(restored.flatten(), display.flatten());
+            assert_eq!(serde_json::to_value(&restored).unwrap(), json);
+        }
+        let matrix = Matrix::new(4, 0, 1);
+        let json = serde_json::to_string(&matrix).unwrap();
+        assert_eq!(json, r#"{"width":4}"#);
+        assert_eq!(serde_json::from_str::<Matrix>(&json).unwrap().width(), 4);
+
+        let mut huge = serde_json::to_value(create_display(0, 0, 1)).unwrap();
+        huge["boundaries"] = serde_json::json!([65536, 65536]);
+        let Err(err) = serde_json::from_value::<Display>(huge) else {
+            panic!("display larger than MAX_PIXELS is accepted");
+        };
+        assert_eq!(
+            err.to_string(),
+            "display 65536x65536 has more than 1073741824 pixels"
+        );
+    }
+}
@@ -0,0 +1,297 @@
+// Хранилища пикселей слоя. Matrix хранит каждый пиксель и подходит для обычных
+// дисплеев. Для огромных холстов, где почти всё закрашено фоном, есть
+// SparseMatrix (только пиксели не цвета фона) и TiledMatrix (плитки 64x64,
+// которые заводятся при первом рисовании в них). Display обобщён по хранилищу,
+// по умолчанию это Matrix.
+
+mod sparse;
+mod tiled;
+
+pub use sparse::SparseMatrix;
+pub use tiled::{TiledMatrix, TILE_SIZE};
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::matrix::{Connectivity, Matrix, Transform, MAX_PIXELS};
+
+/// Прямоугольник пикселей с теми же координатами, что у Matrix:
+/// x - столбец слева направо, y - строка сверху вниз.
+/// colour и set_colour паникуют за краем, как и у Matrix
+pub trait PixelStore {
+    /// Наибольшая площадь, до которой команда RESIZE может растянуть хранилище.
+    /// Больше пикселей не закрашивают и одна FILLRECT или FILL
+    const MAX_AREA: u64;
+
+    /// Хранилище width x height, все пиксели цвета colour
+    fn new(width: u32, height: u32, colour: u8) -> Self;
+
+    fn width(&self) -> usize;
+
+    fn height(&self) -> usize;
+
+    fn colour(&self, x: u64, y: u64) -> u8;
+
+    fn set_colour(&mut self, x: u64, y: u64, colour: u8);
+
+    /// Повёрнутая или отражённая копия
+    fn transform(&self, transform: Transform) -> Self;
+
+    /// Масштабированная копия (ближайший сосед). Пустое хранилище остаётся пустым
+    fn resize(&self, width: usize, height: usize) -> Self;
+
+    /// Копирует поверх себя пиксели top того же размера, кроме цвета transparent
+    fn overlay(&mut self, top: &Self, transparent: Option<u8>);
+
+    /// Какие цвета есть хотя бы в одном пикселе
+    fn colours(&self) -> [bool; 256];
+
+    /// Область w x h с углом в (x, y) как обычная матрица, обрезанная по краям
+    fn copy_region(&self, x: u64, y: u64, w: u64, h: u64) -> Matrix {
+        let (width, height) = (self.width() as u64, self.height() as u64);
+        let (x, y) = (x.min(width), y.min(height));
+        let (x_end, y_end) = (
+            x.saturating_add(w).min(width),
+            y.saturating_add(h).min(height),
+        );
+        let mut region = Matrix::new((x_end - x) as u32, (y_end - y) as u32, 0);
+        for (dx, dy, colour) in region.pixels_mut() {
+            *colour = self.colour(x + dx, y + dy);
+        }
+        region
+    }
+
+    /// Пиксели связной области того же цвета, что и (x, y).
+    /// None, если в области больше limit пикселей
+    fn region(
+        &self,
+        x: u64,
+        y: u64,
+        connectivity: Connectivity,
+        limit: u64,
+    ) -> Option<Vec<(u64, u64)>> {
+        let (width, height) = (self.width() as i64, self.height() as i64);
+        let target = self.colour(x, y);
+        let mut visited = HashSet::from([(x, y)]);
+        let mut stack = vec![(x, y)];
+        let mut region = Vec::new();
+        while let Some((x, y)) = stack.pop() {
+            region.push((x, y));
+            for &(dx, dy) in connectivity.neighbours() {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if !(0..width).contains(&nx) || !(0..height).contains(&ny) {
+                    continue;
+                }
+                let next = (nx as u64, ny as u64);
+                if self.colour(next.0, next.1) == target && visited.insert(next) {
+                    if visited.len() as u64 > limit {
+                        return None;
+                    }
+                    stack.push(next);
+                }
+            }
+        }
+        Some(region)
+    }
+}
+
+impl PixelStore for Matrix {
+    const MAX_AREA: u64 = MAX_PIXELS;
+
+    fn new(width: u32, height: u32, colour: u8) -> Self {
+        Matrix::new(width, height, colour)
+    }
+
+    fn width(&self) -> usize {
+        Matrix::width(self)
+    }
+
+    fn height(&self) -> usize {
+        Matrix::height(self)
+    }
+
+    fn colour(&self, x: u64, y: u64) -> u8 {
+        Matrix::colour(self, x, y)
+    }
+
+    fn set_colour(&mut self, x: u64, y: u64, colour: u8) {
+        Matrix::set_colour(self, x, y, colour)
+    }
+
+    fn transform(&self, transform: Transform) -> Self {
+        Matrix::transform(self, transform)
+    }
+
+    fn resize(&self, width: usize, height: usize) -> Self {
+        Matrix::resize(self, width, height)
+    }
+
+    fn overlay(&mut self, top: &Self, transparent: Option<u8>) {
+        Matrix::overlay(self, top, transparent)
+    }
+
+    fn colours(&self) -> [bool; 256] {
+        let mut colours = [false; 256];
+        for (_, _, colour) in self.pixels() {
+            colours[colour as usize] = true;
+        }
+        colours
+    }
+
+    fn copy_region(&self, x: u64, y: u64, w: u64, h: u64) -> Matrix {
+        Matrix::copy_region(self, x, y, w, h)
+    }
+
+    // Отметки посещённых пикселей в векторе быстрее, чем в HashSet
+    fn region(
+        &self,
+        x: u64,
+        y: u64,
+        connectivity: Connectivity,
+        limit: u64,
+    ) -> Option<Vec<(u64, u64)>> {
+        let region = Matrix::region(self, x, y, connectivity);
+        (region.len() as u64 <= limit).then_some(region)
+    }
+}
+
+// Пиксели нового размера to, которые при масштабировании ближайшим соседом
+// берут цвет из пикселя source старого размера from
+fn scaled(source: u64, from: usize, to: usize) -> Range<u64> {
+    let (from, to) = (from as u64, to as u64);
+    (source * to).div_ceil(from)..((source + 1) * to).div_ceil(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_display_with_store, process_commands, Display, DisplayError};
+
+    // Одна и та же программа на всех хранилищах
+    const PROGRAM: &[u64] = &[
+        1, 1, 1, 3, 6, 2, 3, 4, 0, 4, 2, 6, 3, 2, 7, 8, 3, 11, 3, 2, 1, 5, 0, 12, 0, 14, 20, 7, 11,
+        18, 1, 2, 2, 7, 4, 1,
+    ];
+
+    fn run<S: PixelStore>() -> Display<S> {
+        let mut display = create_display_with_store::<S>(8, 5, 1, Default::default());
+        display.add_layer(0);
+        process_commands(&mut display, PROGRAM.iter().copied()).unwrap();
+        process_commands(&mut display, vec![10, 1, 1, 0, 0, 5, 3, 3, 2]).unwrap();
+        display
+    }
+
+    fn to_matrix(store: &impl PixelStore) -> Matrix {
+        store.copy_region(0, 0, u64::MAX, u64::MAX)
+    }
+
+    #[test]
+    fn test_stores_agree() {
+        let dense = run::<Matrix>();
+        let expected = dense.flatten();
+        assert_eq!((expected.width(), expected.height()), (7, 11));
+        for flattened in [
+            to_matrix(&run::<SparseMatrix>().flatten()),
+            to_matrix(&run::<TiledMatrix>().flatten()),
+        ] {
+            crate::assert_matrix_eq!(flattened, expected);
+        }
+    }
+
+    #[test]
+    fn test_transforms_agree() {
+        let mut dense = Matrix::new(5, 3, 1);
+        let mut sparse = SparseMatrix::new(5, 3, 1);
+        let mut tiled = TiledMatrix::new(5, 3, 1);
+        for (x, y, colour) in [(0, 0, 2), (4, 1, 3), (2, 2, 0)] {
+            PixelStore::set_colour(&mut dense, x, y, colour);
+            sparse.set_colour(x, y, colour);
+            tiled.set_colour(x, y, colour);
+        }
+        for transform in [
+            Transform::Rotate90,
+            Transform::Rotate270,
+            Transform::FlipVertical,
+            Transform::Transpose,
+        ] {
+            let expected = dense.transform(transform);
+            crate::assert_matrix_eq!(to_matrix(&sparse.transform(transform)), expected);
+            crate::assert_matrix_eq!(to_matrix(&tiled.transform(transform)), expected);
+        }
+        for (width, height) in [(7, 2), (2, 9), (13, 3), (0, 4)] {
+            let expected = dense.resize(width, height);
+            crate::assert_matrix_eq!(to_matrix(&sparse.resize(width, height)), expected);
+            crate::assert_matrix_eq!(to_matrix(&tiled.resize(width, height)), expected);
+        }
+    }
+
+    #[test]
+    fn test_huge_commands_are_rejected() {
+        fn check<S: PixelStore>() {
+            let mut display = create_display_with_store::<S>(1000, 1000, 1, Default::default());
+            process_commands(&mut display, vec![1, 5, 5, 2, 2]).unwrap();
+            let err = process_commands(&mut display, vec![20, 4_000_000_000, 4_000_000_000]);
+            assert!(matches!(
+                err,
+                Err(DisplayError::InvalidArgument { name: "area", .. })
+            ));
+
+            let mut display =
+                create_display_with_store::<S>(1_000_000, 1_000_000, 1, Default::default());
+            // Рамка прямоугольника проверяется сразу, область заливки - обходом до предела
+            for stream in [vec![5, 999_999, 999_999, 2], vec![7, 4, 2]] {
+                let err = process_commands(&mut display, stream).unwrap_err();
+                assert_eq!(
+                    err,
+                    DisplayError::TooManyPixels {
+                        at: err.location(),
+                        limit: S::MAX_AREA
+                    }
+                );
+            }
+            // Маленькая область на огромном холсте заливается
+            process_commands(
+                &mut display,
+                vec![3, 0, 2, 3, 3, 2, 0, 3, 1, 0, 0, 1, 1, 1, 7, 4, 2],
+            )
+            .unwrap();
+            assert_eq!(display.matrix().colour(1, 1), 2);
+        }
+        check::<SparseMatrix>();
+        check::<TiledMatrix>();
+    }
+
+    #[test]
+    fn test_huge_canvas() {
+        fn draw<S: PixelStore>() -> Display<S> {
+            let mut display =
+                create_display_with_store::<S>(1_000_000, 1_000_000, 1, Default::default());
+            // Диагональ через весь холст, рамка поверх неё и заливка
+            // треугольника между рамкой и диагональю
+            let stream = vec![
+                3, 999_999, 999_999, 2, 1, 10, 10, 4, 20, 20, 3, 1, 12, 11, 7, 4, 2,
+            ];
+            process_commands(&mut display, stream).unwrap();
+            display
+        }
+        let sparse = draw::<SparseMatrix>();
+        let tiled = draw::<TiledMatrix>();
+        let flattened = tiled.flatten();
+        for (x, y, colour) in [
+            (500_000, 500_000, 2),
+            (999_999, 999_999, 2),
+            (10, 20, 3),
+            (19, 11, 2),
+            (11, 12, 1),
+            (500_001, 500_000, 1),
+        ] {
+            assert_eq!(sparse.matrix().colour(x, y), colour);
+            assert_eq!(tiled.matrix().colour(x, y), colour);
+            assert_eq!(flattened.colour(x, y), colour);
+        }
+        // Диагональ, 38 пикселей рамки вне её и 36 пикселей заливки
+        assert_eq!(sparse.matrix().stored(), 1_000_000 + 38 + 36);
+        // Диагональ задевает по одной плитке на каждые 64 строки
+        assert_eq!(tiled.matrix().tile_count(), 1_000_000_usize.div_ceil(64));
+    }
+}
@@ -0,0 +1,179 @@
+// Разреженное хранилище: в хэше лежат только пиксели, цвет которых отличается
+// от фона, все остальные пиксели считаются закрашенными фоном.
+
+use std::collections::HashMap;
+
+use super::{scaled, PixelStore};
+use crate::matrix::Transform;
+
+/// Хранилище для огромных холстов, где закрашена малая часть пикселей.
+/// Память растёт с числом пикселей не цвета фона, а не с площадью
+#[derive(Debug, Clone)]
+pub struct SparseMatrix {
+    width: usize,
+    height: usize,
+    background: u8,
+    pixels: HashMap<(u64, u64), u8>,
+}
+
+impl SparseMatrix {
+    /// Цвет всех пикселей, которые не хранятся отдельно
+    pub fn background(&self) -> u8 {
+        self.background
+    }
+
+    /// Сколько пикселей отличается от фона
+    pub fn stored(&self) -> usize {
+        self.pixels.len()
+    }
+
+    fn empty(width: usize, height: usize, background: u8) -> Self {
+        Self {
+            width,
+            height,
+            background,
+            pixels: HashMap::new(),
+        }
+    }
+
+    fn check(&self, x: u64, y: u64) {
+        assert!(
+            x < self.width as u64 && y < self.height as u64,
+            "pixel ({x},{y}) is outside {}x{}",
+            self.width,
+            self.height
+        );
+    }
+}
+
+impl PixelStore for SparseMatrix {
+    // Память зависит от нарисованного, а не от площади, но RESIZE, FILLRECT и FILL
+    // перебирают пиксели по одному, поэтому одна команда не трогает больше 2^20
+    const MAX_AREA: u64 = 1 << 20;
+
+    fn new(width: u32, height: u32, colour: u8) -> Self {
+        Self::empty(width as usize, height as usize, colour)
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn colour(&self, x: u64, y: u64) -> u8 {
+        self.check(x, y);
+        self.pixels.get(&(x, y)).copied().unwrap_or(self.background)
+    }
+
+    fn set_colour(&mut self, x: u64, y: u64, colour: u8) {
+        self.check(x, y);
+        if colour == self.background {
+            self.pixels.remove(&(x, y));
+        } else {
+            self.pixels.insert((x, y), colour);
+        }
+    }
+
+    fn transform(&self, transform: Transform) -> Self {
+        let (width, height) = transform.size(self.width, self.height);
+        let mut transformed = Self::empty(width, height, self.background);
+        for (&(x, y), &colour) in &self.pixels {
+            let (x, y) = transform.map(x as usize, y as usize, self.width, self.height);
+            transformed.pixels.insert((x as u64, y as u64), colour);
+        }
+        transformed
+    }
+
+    fn resize(&self, width: usize, height: usize) -> Self {
+        if self.width == 0 || self.height == 0 {
+            return Self::empty(0, 0, self.background);
+        }
+        let mut resized = Self::empty(width, height, self.background);
+        for (&(x, y), &colour) in &self.pixels {
+            for y in scaled(y, self.height, height) {
+                for x in scaled(x, self.width, width) {
+                    resized.pixels.insert((x, y), colour);
+                }
+            }
+        }
+        resized
+    }
+
+    fn overlay(&mut self, top: &Self, transparent: Option<u8>) {
+        if Some(top.background) == transparent {
+            for (&(x, y), &colour) in &top.pixels {
+                if Some(colour) != transparent {
+                    self.set_colour(x, y, colour);
+                }
+            }
+            return;
+        }
+        // Непрозрачный фон top закрывает всё, кроме его прозрачных пикселей
+        let mut result = Self::empty(self.width, self.height, top.background);
+        for (&(x, y), &colour) in &top.pixels {
+            let colour = if Some(colour) == transparent {
+                self.colour(x, y)
+            } else {
+                colour
+            };
+            result.set_colour(x, y, colour);
+        }
+        *self = result;
+    }
+
+    fn colours(&self) -> [bool; 256] {
+        let mut colours = [false; 256];
+        let area = self.width as u64 * self.height as u64;
+        if (self.pixels.len() as u64) < area {
+            colours[self.background as usize] = true;
+        }
+        for &colour in self.pixels.values() {
+            colours[colour as usize] = true;
+        }
+        colours
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_changed_pixels_are_stored() {
+        let mut sparse = SparseMatrix::new(1_000_000, 1_000_000, 1);
+        sparse.set_colour(999_999, 0, 3);
+        sparse.set_colour(5, 5, 2);
+        assert_eq!(sparse.stored(), 2);
+        assert_eq!(sparse.colour(999_999, 0), 3);
+        assert_eq!(sparse.colour(0, 999_999), 1);
+        // Закраска фоном освобождает пиксель
+        sparse.set_colour(5, 5, 1);
+        assert_eq!(sparse.stored(), 1);
+
+        let colours = sparse.colours();
+        assert!(colours[1] && colours[3] && !colours[2]);
+        let rotated = sparse.transform(Transform::Rotate90);
+        assert_eq!(rotated.colour(999_999, 999_999), 3);
+    }
+
+    #[test]
+    fn test_overlay() {
+        let mut bottom = SparseMatrix::new(3, 1, 1);
+        bottom.set_colour(0, 0, 2);
+        // Прозрачный фон: видны только нарисованные пиксели
+        let mut top = SparseMatrix::new(3, 1, 0);
+        top.set_colour(2, 0, 3);
+        bottom.overlay(&top, Some(0));
+        assert_eq!([0, 1, 2].map(|x| bottom.colour(x, 0)), [2, 1, 3]);
+
+        // Непрозрачный фон закрывает всё, кроме прозрачных пикселей
+        let mut cover = SparseMatrix::new(3, 1, 4);
+        cover.set_colour(0, 0, 0);
+        bottom.overlay(&cover, Some(0));
+        assert_eq!([0, 1, 2].map(|x| bottom.colour(x, 0)), [2, 4, 4]);
+        assert_eq!(bottom.background(), 4);
+    }
+}
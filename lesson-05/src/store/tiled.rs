@@ -0,0 +1,219 @@
+// Хранилище из плиток TILE_SIZE x TILE_SIZE. Плитка заводится при первом
+// рисовании в неё не цветом фона, до этого все её пиксели считаются фоном.
+// Заведённые плитки не освобождаются, даже если их снова закрасить фоном.
+
+use std::collections::HashMap;
+
+use super::{scaled, PixelStore};
+use crate::matrix::Transform;
+
+/// Сторона плитки в пикселях
+pub const TILE_SIZE: usize = 64;
+
+type Tile = Box<[u8; TILE_SIZE * TILE_SIZE]>;
+
+/// Хранилище для больших холстов, где рисуют плотно, но в немногих местах.
+/// Пиксели внутри плитки лежат подряд, как в Matrix
+#[derive(Debug, Clone)]
+pub struct TiledMatrix {
+    width: usize,
+    height: usize,
+    background: u8,
+    // Плитки по номеру (столбец, строка) в сетке плиток
+    tiles: HashMap<(u64, u64), Tile>,
+}
+
+impl TiledMatrix {
+    /// Цвет пикселей в плитках, которые ещё не заведены
+    pub fn background(&self) -> u8 {
+        self.background
+    }
+
+    /// Сколько плиток заведено
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    fn empty(width: usize, height: usize, background: u8) -> Self {
+        Self {
+            width,
+            height,
+            background,
+            tiles: HashMap::new(),
+        }
+    }
+
+    // Номер плитки с пикселем (x, y) и место пикселя в ней
+    fn locate(&self, x: u64, y: u64) -> ((u64, u64), usize) {
+        assert!(
+            x < self.width as u64 && y < self.height as u64,
+            "pixel ({x},{y}) is outside {}x{}",
+            self.width,
+            self.height
+        );
+        let size = TILE_SIZE as u64;
+        (
+            (x / size, y / size),
+            ((y % size) * size + x % size) as usize,
+        )
+    }
+
+    // Пиксели (x, y, цвет) заведённых плиток. Части крайних плиток
+    // за границами хранилища пропускаются
+    fn tile_pixels(&self) -> impl Iterator<Item = (u64, u64, u8)> + '_ {
+        let size = TILE_SIZE as u64;
+        self.tiles
+            .iter()
+            .flat_map(move |(&(column, row), tile)| {
+                tile.iter().enumerate().map(move |(index, &colour)| {
+                    let index = index as u64;
+                    (
+                        column * size + index % size,
+                        row * size + index / size,
+                        colour,
+                    )
+                })
+            })
+            .filter(|&(x, y, _)| x < self.width as u64 && y < self.height as u64)
+    }
+}
+
+impl PixelStore for TiledMatrix {
+    // Плитки заводятся по мере рисования, но одна команда закрашивает
+    // пиксели по одному, как и в SparseMatrix
+    const MAX_AREA: u64 = 1 << 20;
+
+    fn new(width: u32, height: u32, colour: u8) -> Self {
+        Self::empty(width as usize, height as usize, colour)
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn colour(&self, x: u64, y: u64) -> u8 {
+        let (tile, index) = self.locate(x, y);
+        self.tiles
+            .get(&tile)
+            .map_or(self.background, |tile| tile[index])
+    }
+
+    fn set_colour(&mut self, x: u64, y: u64, colour: u8) {
+        let (tile, index) = self.locate(x, y);
+        if colour == self.background && !self.tiles.contains_key(&tile) {
+            return;
+        }
+        let background = self.background;
+        self.tiles
+            .entry(tile)
+            .or_insert_with(|| Box::new([background; TILE_SIZE * TILE_SIZE]))[index] = colour;
+    }
+
+    fn transform(&self, transform: Transform) -> Self {
+        let (width, height) = transform.size(self.width, self.height);
+        let mut transformed = Self::empty(width, height, self.background);
+        for (x, y, colour) in self.tile_pixels() {
+            let (x, y) = transform.map(x as usize, y as usize, self.width, self.height);
+            transformed.set_colour(x as u64, y as u64, colour);
+        }
+        transformed
+    }
+
+    fn resize(&self, width: usize, height: usize) -> Self {
+        if self.width == 0 || self.height == 0 {
+            return Self::empty(0, 0, self.background);
+        }
+        let mut resized = Self::empty(width, height, self.background);
+        for (x, y, colour) in self.tile_pixels() {
+            for y in scaled(y, self.height, height) {
+                for x in scaled(x, self.width, width) {
+                    resized.set_colour(x, y, colour);
+                }
+            }
+        }
+        resized
+    }
+
+    fn overlay(&mut self, top: &Self, transparent: Option<u8>) {
+        // Непрозрачный фон top закрывает всё, что лежит вне его плиток
+        let covered = Some(top.background) != transparent;
+        let below = self.background;
+        if covered {
+            self.background = top.background;
+        }
+        let mut tiles = HashMap::with_capacity(top.tiles.len());
+        for (&key, top_tile) in &top.tiles {
+            let mut tile = self
+                .tiles
+                .remove(&key)
+                .unwrap_or_else(|| Box::new([below; TILE_SIZE * TILE_SIZE]));
+            for (pixel, &colour) in tile.iter_mut().zip(top_tile.iter()) {
+                if Some(colour) != transparent {
+                    *pixel = colour;
+                }
+            }
+            tiles.insert(key, tile);
+        }
+        if !covered {
+            tiles.extend(self.tiles.drain());
+        }
+        self.tiles = tiles;
+    }
+
+    fn colours(&self) -> [bool; 256] {
+        let mut colours = [false; 256];
+        let grid = self.width.div_ceil(TILE_SIZE) as u64 * self.height.div_ceil(TILE_SIZE) as u64;
+        if (self.tiles.len() as u64) < grid {
+            colours[self.background as usize] = true;
+        }
+        for (_, _, colour) in self.tile_pixels() {
+            colours[colour as usize] = true;
+        }
+        colours
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiles_are_lazy() {
+        let mut tiled = TiledMatrix::new(1_000_000, 1_000_000, 1);
+        assert_eq!(tiled.tile_count(), 0);
+        // Фоном в пустую плитку рисовать незачем
+        tiled.set_colour(10, 10, 1);
+        assert_eq!(tiled.tile_count(), 0);
+        tiled.set_colour(10, 10, 2);
+        tiled.set_colour(63, 63, 3);
+        tiled.set_colour(64, 63, 3);
+        assert_eq!(tiled.tile_count(), 2);
+        assert_eq!(tiled.colour(10, 10), 2);
+        assert_eq!(tiled.colour(11, 10), 1);
+        assert_eq!(tiled.colour(999_999, 999_999), 1);
+    }
+
+    #[test]
+    fn test_edge_tiles() {
+        // 70x3: вторая плитка в строке заполнена только на 6 столбцов
+        let mut tiled = TiledMatrix::new(70, 3, 0);
+        tiled.set_colour(69, 2, 5);
+        let colours = tiled.colours();
+        assert!(colours[0] && colours[5]);
+        assert_eq!(colours.iter().filter(|&&used| used).count(), 2);
+
+        let flipped = tiled.transform(Transform::FlipHorizontal);
+        assert_eq!(flipped.colour(0, 2), 5);
+        assert_eq!(flipped.tile_count(), 1);
+
+        let mut cover = TiledMatrix::new(70, 3, 4);
+        cover.set_colour(69, 2, 0);
+        tiled.overlay(&cover, Some(0));
+        assert_eq!(tiled.colour(69, 2), 5);
+        assert_eq!(tiled.colour(0, 0), 4);
+    }
+}
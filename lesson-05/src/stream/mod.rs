@@ -0,0 +1,211 @@
+// Потоковое применение команд из io::BufRead. Числа читаются по одному,
+// так что память не растёт с длиной потока (история дисплея ограничена
+// своим лимитом, см. Display::set_history_limit).
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::iter;
+
+use crate::store::PixelStore;
+use crate::{process_commands, Display, DisplayError};
+
+// Длиннее u64 всё равно не бывает, остаток токена в ошибку не попадает
+const MAX_TOKEN: usize = 32;
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    /// position - номер числа в потоке, совпадает со смещением в Location
+    InvalidNumber {
+        position: usize,
+        token: String,
+    },
+    Display(DisplayError),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(err) => write!(f, "i/o error: {err}"),
+            StreamError::InvalidNumber { position, token } => {
+                write!(
+                    f,
+                    "value at offset {position}: expected a number, found '{token}'"
+                )
+            }
+            StreamError::Display(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for StreamError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StreamError::Io(err) => Some(err),
+            StreamError::InvalidNumber { .. } => None,
+            StreamError::Display(err) => Some(err),
+        }
+    }
+}
+
+/// Числа через пробельные символы из BufRead
+pub struct Numbers<R> {
+    reader: R,
+    position: usize,
+}
+
+impl<R: BufRead> Numbers<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            position: 0,
+        }
+    }
+
+    // Следующее слово: начало (не длиннее MAX_TOKEN) и полная длина
+    fn token(&mut self) -> io::Result<(String, usize)> {
+        let mut token = String::new();
+        let mut len = 0;
+        loop {
+            let buffer = match self.reader.fill_buf() {
+                Ok(buffer) => buffer,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if buffer.is_empty() {
+                return Ok((token, len));
+            }
+            let mut used = 0;
+            let mut done = false;
+            for &byte in buffer {
+                used += 1;
+                if byte.is_ascii_whitespace() {
+                    if len > 0 {
+                        done = true;
+                        break;
+                    }
+                } else {
+                    len += 1;
+                    if token.len() < MAX_TOKEN {
+                        token.push(byte as char);
+                    }
+                }
+            }
+            self.reader.consume(used);
+            if done {
+                return Ok((token, len));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Numbers<R> {
+    type Item = Result<u64, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (token, len) = match self.token() {
+            Ok(token) => token,
+            Err(err) => return Some(Err(StreamError::Io(err))),
+        };
+        if len == 0 {
+            return None;
+        }
+        let position = self.position;
+        self.position += 1;
+        Some(
+            token
+                .parse()
+                .ok()
+                .filter(|_| len == token.len())
+                .ok_or(StreamError::InvalidNumber { position, token }),
+        )
+    }
+}
+
+/// Применяет числовой поток команд из reader по мере чтения
+pub fn process_reader<S: PixelStore>(
+    display: &mut Display<S>,
+    reader: impl BufRead,
+) -> Result<(), StreamError> {
+    let mut numbers = Numbers::new(reader);
+    let mut error = None;
+    // Ошибка чтения обрывает поток, но сообщается она, а не оборванная команда
+    let values = iter::from_fn(|| match numbers.next()? {
+        Ok(value) => Some(value),
+        Err(err) => {
+            error = Some(err);
+            None
+        }
+    });
+    let result = process_commands(display, values);
+    match error {
+        Some(err) => Err(err),
+        None => result.map_err(StreamError::Display),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_display;
+    use crate::matrix::Matrix;
+    use std::io::{BufReader, Read};
+
+    #[test]
+    fn test_process_reader() {
+        let mut display = create_display(4, 4, 1);
+        // Маленький буфер, чтобы числа разрывались между чтениями
+        let input = BufReader::with_capacity(3, "1 2 2\n2  3\t".as_bytes());
+        process_reader(&mut display, input).unwrap();
+        let mut expected = Matrix::new(4, 4, 1);
+        expected.set_colour(2, 2, 3);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut display = create_display(4, 4, 1);
+        let err = process_reader(&mut display, "1 2 x 2 3".as_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "value at offset 2: expected a number, found 'x'"
+        );
+        let long = "9".repeat(100);
+        let err = process_reader(&mut display, long.as_bytes()).unwrap_err();
+        assert!(
+            matches!(err, StreamError::InvalidNumber { position: 0, ref token } if token.len() == MAX_TOKEN)
+        );
+        let err = process_reader(&mut display, "1 2 9 2 3".as_bytes()).unwrap_err();
+        assert!(matches!(
+            err,
+            StreamError::Display(DisplayError::OutOfBounds { .. })
+        ));
+    }
+
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("disconnected"))
+        }
+    }
+
+    #[test]
+    fn test_io_error_wins_over_truncation() {
+        let mut display = create_display(4, 4, 1);
+        let input = BufReader::new("1 2 ".as_bytes().chain(Broken));
+        let err = process_reader(&mut display, input).unwrap_err();
+        assert!(matches!(err, StreamError::Io(_)));
+    }
+
+    #[test]
+    fn test_long_iterator() {
+        let mut display = create_display(4, 4, 1);
+        let commands = iter::repeat([1, 3, 3, 2, 2, 2, 3]).flatten().take(700_000);
+        process_commands(&mut display, commands).unwrap();
+        let mut expected = Matrix::new(4, 4, 1);
+        expected.set_colour(3, 3, 3);
+        crate::assert_matrix_eq!(*display.matrix(), expected);
+    }
+}